@@ -1,23 +1,154 @@
-use clap::{ArgGroup, Parser};
-use git_changes::{self, FileStatus};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use git_changes::impact::{TestImpact, TestRunner};
+use git_changes::policy::{Policy, Severity, Violation};
+use git_changes::processor::{CommitAttribution, GitChangesProcessor};
+use git_changes::secrets::SecretScanner;
+use git_changes::{
+    self, cache, ChangeSet, Commit, DiffSpec, Error, ExportOptions, FileChange, FileStatus,
+    PathFilter,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{fmt, EnvFilter};
 
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable summary
+    Text,
+    /// One JSON object per changed file. Without an output directory the changes are streamed
+    /// from git as they are produced, using constant memory.
+    Ndjson,
+    /// A JSON object with the changes and the commits of the comparison, each commit listing
+    /// the paths it changed
+    Json,
+}
+
+/// How the commits of each changed file are found
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FileCommitsMode {
+    /// One `git log` over the range
+    Cheap,
+    /// One `git log` per changed file, which also finds merges that changed it
+    Precise,
+}
+
+impl From<FileCommitsMode> for CommitAttribution {
+    fn from(mode: FileCommitsMode) -> Self {
+        match mode {
+            FileCommitsMode::Cheap => Self::Cheap,
+            FileCommitsMode::Precise => Self::Precise,
+        }
+    }
+}
+
+/// Options for opening the repository
+#[derive(Args)]
+struct RepoArgs {
+    /// Git repository (HTTPS/SSH URL or local path)
+    #[arg(short, long, required = true)]
+    repo: Option<String>,
+
+    /// Number of worker threads used to export files
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Maximum number of commits fetched to find the merge base in a shallow clone (0 disables)
+    #[arg(long, default_value_t = git_changes::processor::DEFAULT_DEEPEN_LIMIT)]
+    deepen_limit: u32,
+
+    /// Kill git commands that run longer than this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Report the file changes inside modified submodules that are checked out locally
+    #[arg(long)]
+    recurse_submodules: bool,
+
+    /// Annotate each change with its owners from the `CODEOWNERS` file of the base revision
+    #[arg(long)]
+    code_owners: bool,
+
+    /// Annotate each change with the commits of the comparison that changed it
+    #[arg(long, value_enum, value_name = "MODE")]
+    file_commits: Option<FileCommitsMode>,
+
+    #[command(flatten)]
+    clone: CloneArgs,
+
+    #[command(flatten)]
+    auth: AuthArgs,
+}
+
+/// Credentials for private remote repositories
+#[derive(Args)]
+#[command(next_help_heading = "Authentication")]
+struct AuthArgs {
+    /// Token for HTTPS remotes. Prefer the environment variable, command lines are visible to
+    /// other processes.
+    #[arg(long, env = "GIT_CHANGES_HTTPS_TOKEN", hide_env_values = true)]
+    https_token: Option<String>,
+
+    /// User name sent with the HTTPS token (defaults to `x-access-token`)
+    #[arg(long)]
+    https_username: Option<String>,
+
+    /// Private key for SSH remotes
+    #[arg(long)]
+    ssh_key: Option<PathBuf>,
+
+    /// CA bundle used to verify HTTPS remotes
+    #[arg(long)]
+    ca_bundle: Option<PathBuf>,
+}
+
+/// Options for cloning remote repositories
+#[derive(Args)]
+#[command(next_help_heading = "Remote clone")]
+struct CloneArgs {
+    /// Clone remote repositories shallowly, with this many commits per branch
+    #[arg(long)]
+    clone_depth: Option<u32>,
+
+    /// Partial clone filter for remote repositories, e.g. `blob:none` or `tree:0`
+    #[arg(long, value_name = "FILTER")]
+    clone_filter: Option<String>,
+
+    /// Clone only one branch: the one set with `--clone-branch`, or the remote's default branch
+    #[arg(long)]
+    single_branch: bool,
+
+    /// Branch to check out after cloning, and the only one fetched with `--single-branch`
+    #[arg(long, value_name = "BRANCH")]
+    clone_branch: Option<String>,
+
+    /// Clone without checking out files; branches are compared without being checked out
+    #[arg(long)]
+    no_checkout: bool,
+
+    /// Keep a mirror of remote repositories in this directory and reuse it on later runs
+    #[arg(long, env = "GIT_CHANGES_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Prune the least recently used mirrors once the cache grows beyond this size (e.g. `10G`)
+    #[arg(long, value_parser = parse_size, requires = "cache_dir")]
+    cache_max_size: Option<u64>,
+}
+
+/// What to compare
+#[derive(Args)]
 #[command(group(
     ArgGroup::new("target")
         .required(true)
-        .args(["branch", "commit"]),
+        .args(["branch", "commit", "range", "worktree", "staged"]),
 ))]
-struct Cli {
-    /// Git repository (HTTPS/SSH URL or local path)
-    #[arg(short, long)]
-    repo: String,
-
+struct TargetArgs {
     /// Branch to analyze (if not provided, will try to detect from CI environment)
-    #[arg(short, long, group = "target")]
+    #[arg(short, long)]
     branch: Option<String>,
 
     /// Target branch to compare against (defaults to origin/HEAD or origin/main if not found)
@@ -25,41 +156,649 @@ struct Cli {
     target_branch: Option<String>,
 
     /// Commit to analyze
-    #[arg(short, long, group = "target")]
+    #[arg(short, long)]
     commit: Option<String>,
 
+    /// Revision range to analyze: `from..to` compares the two trees, `base...head` compares
+    /// head with its merge base
+    #[arg(long, value_parser = parse_range)]
+    range: Option<DiffSpec>,
+
+    /// Analyze uncommitted changes to tracked files in the working tree
+    #[arg(long)]
+    worktree: bool,
+
+    /// Analyze staged changes
+    #[arg(long)]
+    staged: bool,
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    repo: RepoArgs,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
     /// Output directory for changes (if not provided, only lists changes)
     #[arg(short, long)]
     output_dir: Option<PathBuf>,
 
+    /// Write a JSON manifest of the export to this file, listing the exported changes and the
+    /// Git LFS objects that were exported as pointers
+    #[arg(long, value_name = "FILE", requires = "output_dir")]
+    manifest: Option<PathBuf>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    format: OutputFormat,
+
+    /// Leave out changed paths matching this glob (repeatable), e.g. `docs/**` or `*.md`
+    #[arg(short, long, value_name = "GLOB")]
+    ignore: Vec<String>,
+
+    /// Exit with 1 when there are changes (after filters) and 0 when there are none, like
+    /// `git diff --exit-code`. Errors always exit with 2 or more.
+    #[arg(long)]
+    exit_code: bool,
+
     /// Log level
     #[arg(global = true, short, long, value_enum, default_value = "error")]
     log: LevelFilter,
 }
 
-fn print_changes_summary(changes: &std::collections::HashMap<String, git_changes::FileChange>) {
+/// Output format of the `check` subcommand
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CheckFormat {
+    /// Human readable report
+    Text,
+    /// A JSON array of violations
+    Json,
+    /// GitHub Actions workflow commands, shown as annotations on the pull request
+    Github,
+}
+
+/// Arguments of the `check` subcommand
+#[derive(Args)]
+struct CheckArgs {
+    #[command(flatten)]
+    repo: RepoArgs,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Policy file with the rules to check
+    #[arg(short, long, default_value = ".git-changes.toml")]
+    policy: PathBuf,
+
+    /// Leave out changed paths matching this glob (repeatable)
+    #[arg(short, long, value_name = "GLOB")]
+    ignore: Vec<String>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    format: CheckFormat,
+}
+
+/// Output format of the `owners` subcommand
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OwnersFormat {
+    /// Changed files listed under each owner
+    Text,
+    /// A JSON object with the changed paths of each owner and the unowned paths
+    Json,
+}
+
+/// Arguments of the `owners` subcommand
+#[derive(Args)]
+struct OwnersArgs {
+    #[command(flatten)]
+    repo: RepoArgs,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Leave out changed paths matching this glob (repeatable)
+    #[arg(short, long, value_name = "GLOB")]
+    ignore: Vec<String>,
+
+    /// Only report the changed files that have no owner
+    #[arg(long)]
+    unowned: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    format: OwnersFormat,
+}
+
+/// Output format of the `impact` subcommand
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ImpactFormat {
+    /// One test per line
+    Lines,
+    /// A JSON object with the selected tests and the changed paths no test maps to
+    Json,
+    /// Arguments for `cargo test`, one line per invocation
+    Cargo,
+    /// Arguments for `pytest`
+    Pytest,
+    /// Arguments for `jest`
+    Jest,
+}
+
+/// Arguments of the `impact` subcommand
+#[derive(Args)]
+struct ImpactArgs {
+    #[command(flatten)]
+    repo: RepoArgs,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Leave out changed paths matching this glob (repeatable)
+    #[arg(short, long, value_name = "GLOB")]
+    ignore: Vec<String>,
+
+    /// Configuration file with `[[test_mapping]]` tables (`.git-changes.toml` when it exists)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Coverage data mapping tests to the files they execute: lcov with test names, or JSON
+    /// (repeatable)
+    #[arg(long, value_name = "FILE")]
+    coverage: Vec<PathBuf>,
+
+    /// Do not select tests by naming conventions
+    #[arg(long)]
+    no_conventions: bool,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "lines")]
+    format: ImpactFormat,
+}
+
+/// Output format of the `scan-secrets` subcommand
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SecretsFormat {
+    /// One finding per line, as `path:line:column`
+    Text,
+    /// A JSON array of findings
+    Json,
+    /// A SARIF log, for code scanning services such as GitHub code scanning
+    Sarif,
+}
+
+/// Arguments of the `scan-secrets` subcommand
+#[derive(Args)]
+struct ScanSecretsArgs {
+    #[command(flatten)]
+    repo: RepoArgs,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Leave out changed paths matching this glob (repeatable)
+    #[arg(short, long, value_name = "GLOB")]
+    ignore: Vec<String>,
+
+    /// Configuration file with a `[secrets]` table (`.git-changes.toml` when it exists)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// File listing allowed paths, regexes and values (`.git-changes-allowlist` when it exists)
+    #[arg(long, value_name = "FILE")]
+    allowlist: Option<PathBuf>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "text")]
+    format: SecretsFormat,
+}
+
+/// Output format of the `changelog` subcommand
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ChangelogFormat {
+    /// A Markdown changelog grouped by commit type
+    Markdown,
+    /// A JSON object with the suggested version bump, overall and per package, and the parsed
+    /// commits
+    Json,
+}
+
+/// Arguments of the `changelog` subcommand
+#[derive(Args)]
+struct ChangelogArgs {
+    #[command(flatten)]
+    repo: RepoArgs,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// Leave out changed paths matching this glob (repeatable); commits that only changed
+    /// ignored paths do not affect the packages
+    #[arg(short, long, value_name = "GLOB")]
+    ignore: Vec<String>,
+
+    /// Heading of the changelog, e.g. the version being released
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value = "markdown")]
+    format: ChangelogFormat,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check the changes against the rules of a policy file; exits with 1 when an error-level
+    /// rule is violated
+    Check(Box<CheckArgs>),
+
+    /// Group the changed files by their owners in `CODEOWNERS`
+    Owners(Box<OwnersArgs>),
+
+    /// List the tests affected by the changes
+    Impact(Box<ImpactArgs>),
+
+    /// Write a changelog from the Conventional Commits of the comparison, and suggest the
+    /// semantic version bump
+    Changelog(Box<ChangelogArgs>),
+
+    /// Scan the added lines for secrets and credentials; exits with 1 when any is found
+    ScanSecrets(Box<ScanSecretsArgs>),
+
+    /// Manage the cache of remote repositories
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove the least recently used mirrors until the cache fits in the size limit.
+    /// Mirrors in use by a running process are kept.
+    Prune {
+        /// Cache directory
+        #[arg(long, env = "GIT_CHANGES_CACHE_DIR")]
+        cache_dir: PathBuf,
+
+        /// Size the cache may keep (e.g. `10G`); removes every unused mirror by default
+        #[arg(long, value_parser = parse_size, default_value = "0")]
+        max_size: u64,
+    },
+}
+
+fn parse_range(range: &str) -> Result<DiffSpec, String> {
+    DiffSpec::parse_range(range)
+        .ok_or_else(|| format!("expected `from..to` or `base...head`, got `{range}`"))
+}
+
+/// Parses a size in bytes with an optional binary suffix: `512`, `100K`, `20M`, `10G`, `1T`
+fn parse_size(size: &str) -> Result<u64, String> {
+    let trimmed = size
+        .trim()
+        .trim_end_matches(['B', 'b'])
+        .trim_end_matches('i');
+    let (digits, shift) = match trimmed.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 10),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 20),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 30),
+        Some('T') => (&trimmed[..trimmed.len() - 1], 40),
+        _ => (trimmed, 0),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size `{size}`, expected e.g. `500M` or `10G`"))
+}
+
+impl RepoArgs {
+    /// Opens the repository, cloning it when it is remote, and leaves out the changed paths
+    /// matching the `ignore` globs
+    fn processor(&self, ignore: &[String]) -> git_changes::Result<GitChangesProcessor> {
+        let mut builder = GitChangesProcessor::builder()
+            .path_filter(PathFilter::new().ignore(ignore.iter().cloned())?)
+            .submodule_recursion(self.recurse_submodules)
+            .code_owners(self.code_owners)
+            .commit_attribution(self.file_commits.map(Into::into))
+            .jobs(self.jobs)
+            .deepen_limit(self.deepen_limit)
+            .single_branch(self.clone.single_branch)
+            .no_checkout(self.clone.no_checkout);
+        // Only unset for the top-level arguments when a subcommand was given
+        if let Some(repo) = &self.repo {
+            builder = builder.repo(repo);
+        }
+        if let Some(depth) = self.clone.clone_depth {
+            builder = builder.clone_depth(depth);
+        }
+        if let Some(filter) = &self.clone.clone_filter {
+            builder = builder.clone_filter(filter);
+        }
+        if let Some(branch) = &self.clone.clone_branch {
+            builder = builder.clone_branch(branch);
+        }
+        if let Some(cache_dir) = &self.clone.cache_dir {
+            builder = builder.cache_dir(cache_dir);
+        }
+        if let Some(max_size) = self.clone.cache_max_size {
+            builder = builder.cache_max_size(max_size);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        if let Some(token) = &self.auth.https_token {
+            builder = builder.https_token(token);
+        }
+        if let Some(username) = &self.auth.https_username {
+            builder = builder.https_username(username);
+        }
+        if let Some(ssh_key) = &self.auth.ssh_key {
+            builder = builder.ssh_key(ssh_key);
+        }
+        if let Some(ca_bundle) = &self.auth.ca_bundle {
+            builder = builder.ca_bundle(ca_bundle);
+        }
+        builder.build()
+    }
+}
+
+impl TargetArgs {
+    /// Maps the target flags onto the comparison they select
+    fn diff_spec(&self) -> DiffSpec {
+        if let Some(branch) = &self.branch {
+            return self.target_branch.as_ref().map_or_else(
+                || DiffSpec::branch_vs_default(branch),
+                |target_branch| DiffSpec::branch(branch, target_branch),
+            );
+        }
+        if let Some(commit) = &self.commit {
+            return DiffSpec::commit(commit);
+        }
+        if let Some(range) = &self.range {
+            return range.clone();
+        }
+        if self.worktree {
+            return DiffSpec::Worktree;
+        }
+        if self.staged {
+            return DiffSpec::Index;
+        }
+        unreachable!("ArgGroup ensures exactly one target is provided")
+    }
+}
+
+/// Output of `--format json`
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    changes: &'a ChangeSet,
+    commits: Vec<Commit>,
+}
+
+/// Prints one JSON object per change and returns how many were printed
+fn print_ndjson(
+    changes: impl Iterator<Item = git_changes::Result<FileChange>>,
+) -> git_changes::Result<usize> {
+    let mut stdout = std::io::stdout().lock();
+    let mut count = 0;
+    for change in changes {
+        serde_json::to_writer(&mut stdout, &change?).map_err(std::io::Error::from)?;
+        stdout.write_all(b"\n")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Selects and prints the tests affected by the changes. Returns false when some of the
+/// selected tests cannot be passed to the chosen runner.
+fn run_impact(args: &ImpactArgs) -> git_changes::Result<bool> {
+    let mut impact = TestImpact::new().with_conventions(!args.no_conventions);
+    let default_config = PathBuf::from(".git-changes.toml");
+    match &args.config {
+        Some(config) => impact = impact.load(config)?,
+        None if default_config.is_file() => impact = impact.load(&default_config)?,
+        None => {}
+    }
+    for coverage in &args.coverage {
+        impact = impact.load(coverage)?;
+    }
+
+    let selection = args
+        .repo
+        .processor(&args.ignore)?
+        .select_tests(&args.target.diff_spec(), &impact)?;
+    let runner = match args.format {
+        ImpactFormat::Lines => {
+            for test in &selection.tests {
+                println!("{test}");
+            }
+            None
+        }
+        ImpactFormat::Json => {
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &selection)
+                .map_err(std::io::Error::from)?;
+            println!();
+            return Ok(true);
+        }
+        ImpactFormat::Cargo => Some(TestRunner::Cargo),
+        ImpactFormat::Pytest => Some(TestRunner::Pytest),
+        ImpactFormat::Jest => Some(TestRunner::Jest),
+    };
+    if let Some(runner) = runner {
+        for args in selection.to_invocations(runner) {
+            println!("{}", args.join(" "));
+        }
+    }
+    let unrunnable = runner.map_or_else(Vec::new, |runner| selection.unrunnable(runner));
+    if !selection.unmapped.is_empty() {
+        eprintln!(
+            "note: {} changed file(s) map to no test: {}",
+            selection.unmapped.len(),
+            selection.unmapped.join(", ")
+        );
+    }
+    if !unrunnable.is_empty() {
+        eprintln!(
+            "error: {} selected test(s) cannot be passed to the test runner: {}",
+            unrunnable.len(),
+            unrunnable.join(", ")
+        );
+    }
+    Ok(unrunnable.is_empty())
+}
+
+/// Scans the added lines for secrets, prints the findings and returns whether there were any
+fn run_scan_secrets(args: &ScanSecretsArgs) -> git_changes::Result<bool> {
+    let mut scanner = SecretScanner::new();
+    let default_config = PathBuf::from(".git-changes.toml");
+    match &args.config {
+        Some(config) => scanner = scanner.load(config)?,
+        None if default_config.is_file() => scanner = scanner.load(&default_config)?,
+        None => {}
+    }
+    let default_allowlist = PathBuf::from(".git-changes-allowlist");
+    match &args.allowlist {
+        Some(allowlist) => scanner = scanner.load_allowlist(allowlist)?,
+        None if default_allowlist.is_file() => {
+            scanner = scanner.load_allowlist(&default_allowlist)?;
+        }
+        None => {}
+    }
+
+    let findings = args
+        .repo
+        .processor(&args.ignore)?
+        .scan_secrets(&args.target.diff_spec(), &scanner)?;
+    match args.format {
+        SecretsFormat::Text => {
+            for finding in &findings {
+                println!(
+                    "{}:{}:{}: {} ({}): {}",
+                    finding.path,
+                    finding.line,
+                    finding.column,
+                    finding.description,
+                    finding.rule,
+                    finding.redacted
+                );
+            }
+            if findings.is_empty() {
+                println!("No secrets found in the added lines");
+            } else {
+                println!("\n{} secret(s) found", findings.len());
+            }
+        }
+        SecretsFormat::Json => {
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &findings)
+                .map_err(std::io::Error::from)?;
+            println!();
+        }
+        SecretsFormat::Sarif => {
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &scanner.sarif(&findings))
+                .map_err(std::io::Error::from)?;
+            println!();
+        }
+    }
+    Ok(!findings.is_empty())
+}
+
+fn print_owners(
+    changes: &ChangeSet,
+    unowned_only: bool,
+    format: OwnersFormat,
+) -> git_changes::Result<()> {
+    let groups = if unowned_only {
+        BTreeMap::new()
+    } else {
+        changes.group_by_owner()
+    };
+    let unowned: Vec<&str> = changes
+        .unowned()
+        .map(|change| change.path.as_str())
+        .collect();
+
+    if format == OwnersFormat::Json {
+        let owners: BTreeMap<&str, Vec<&str>> = groups
+            .iter()
+            .map(|(owner, changes)| (owner.as_str(), changes.paths().collect()))
+            .collect();
+        let report = serde_json::json!({ "owners": owners, "unowned": unowned });
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &report)
+            .map_err(std::io::Error::from)?;
+        println!();
+        return Ok(());
+    }
+
+    for (owner, changes) in &groups {
+        println!("{owner} ({})", changes.len());
+        for path in changes.paths() {
+            println!("  {path}");
+        }
+    }
+    if !unowned.is_empty() {
+        println!("Unowned ({})", unowned.len());
+        for path in unowned {
+            println!("  {path}");
+        }
+    }
+    Ok(())
+}
+
+fn print_violations(violations: &[Violation], num_rules: usize) {
+    if violations.is_empty() {
+        println!("✅ All {num_rules} rule(s) passed");
+        return;
+    }
+    for violation in violations {
+        let icon = match violation.severity {
+            Severity::Error => "❌",
+            Severity::Warning => "⚠️ ",
+        };
+        println!("{icon} [{}] {}", violation.rule, violation.message);
+        for path in &violation.paths {
+            println!("    {path}");
+        }
+    }
+    let errors = violations
+        .iter()
+        .filter(|violation| violation.severity == Severity::Error)
+        .count();
+    println!(
+        "\n{errors} error(s), {} warning(s)",
+        violations.len() - errors
+    );
+}
+
+/// Prints violations as GitHub Actions workflow commands, one annotation per offending file
+fn print_github_annotations(violations: &[Violation]) {
+    // See https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+    let escape_data = |value: &str| {
+        value
+            .replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A")
+    };
+    let escape_property = |value: &str| escape_data(value).replace(':', "%3A").replace(',', "%2C");
+    for violation in violations {
+        let command = match violation.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let title = escape_property(&violation.rule);
+        let message = escape_data(&violation.message);
+        if violation.paths.is_empty() {
+            println!("::{command} title={title}::{message}");
+        }
+        for path in &violation.paths {
+            println!(
+                "::{command} file={},title={title}::{message}",
+                escape_property(path)
+            );
+        }
+    }
+}
+
+fn print_changes_summary(changes: &ChangeSet) {
     let mut added = Vec::new();
     let mut modified = Vec::new();
     let mut deleted = Vec::new();
+    let mut type_changed = Vec::new();
 
-    for (path, change) in changes {
+    for change in changes {
+        let path = &change.path;
         match change.status {
-            FileStatus::Added => added.push(path),
-            FileStatus::Modified => modified.push(path),
-            FileStatus::Deleted => deleted.push(path),
+            FileStatus::Added => added.push(path.clone()),
+            FileStatus::Modified if change.mode_changed() => modified.push(format!(
+                "{path} (mode {} -> {})",
+                change.old_mode.as_deref().unwrap_or_default(),
+                change.new_mode.as_deref().unwrap_or_default()
+            )),
+            FileStatus::Modified => modified.push(path.clone()),
+            FileStatus::Deleted => deleted.push(path.clone()),
+            FileStatus::TypeChanged => {
+                type_changed.push(format!("{path} ({:?})", change.entry_kind));
+            }
         }
     }
 
-    added.sort();
-    modified.sort();
-    deleted.sort();
-
+    let stats = changes.stats();
     println!("\n📊 Changes Summary:");
     println!("==================");
-    println!("Total files: {}", changes.len());
-    println!("  Added:    {}", added.len());
-    println!("  Modified: {}", modified.len());
-    println!("  Deleted:  {}", deleted.len());
+    println!("Total files: {}", stats.total);
+    println!("  Added:    {}", stats.added);
+    println!("  Modified: {}", stats.modified);
+    println!("  Deleted:  {}", stats.deleted);
+    if stats.type_changed > 0 {
+        println!("  Type changed: {}", stats.type_changed);
+    }
 
     if !added.is_empty() {
         println!("\n✨ Added Files:");
@@ -81,10 +820,54 @@ fn print_changes_summary(changes: &std::collections::HashMap<String, git_changes
             println!("  - {path}");
         }
     }
+
+    if !type_changed.is_empty() {
+        println!("\n🔀 Type Changed Files:");
+        for path in type_changed {
+            println!("  ! {path}");
+        }
+    }
+
+    let submodules: Vec<_> = changes
+        .iter()
+        .filter_map(|change| change.submodule.as_ref().map(|sub| (&change.path, sub)))
+        .collect();
+
+    if !submodules.is_empty() {
+        println!("\n📦 Submodules:");
+        for (path, submodule) in submodules {
+            println!(
+                "  * {path} ({} -> {})",
+                submodule.old_commit.as_deref().unwrap_or("none"),
+                submodule.new_commit.as_deref().unwrap_or("none")
+            );
+            for nested in &submodule.changes {
+                let marker = match nested.status {
+                    FileStatus::Added => '+',
+                    FileStatus::Modified => '~',
+                    FileStatus::Deleted => '-',
+                    FileStatus::TypeChanged => '!',
+                };
+                println!("      {marker} {}", nested.path);
+            }
+        }
+    }
+
+    let unresolved_lfs: Vec<_> = changes
+        .unresolved_lfs()
+        .filter_map(|change| Some((&change.path, change.lfs_pointer.as_ref()?)))
+        .collect();
+
+    if !unresolved_lfs.is_empty() {
+        println!("\n⚠️  Unresolved LFS Objects (exported as pointers):");
+        for (path, pointer) in unresolved_lfs {
+            println!("  ? {path} ({}, {} bytes)", pointer.oid, pointer.size);
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> git_changes::Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
 
     // Initialize logging
@@ -98,33 +881,177 @@ async fn main() -> git_changes::Result<()> {
         .pretty()
         .init();
 
-    let processor = git_changes::new(&cli.repo)?;
+    match run(&cli) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: {error}");
+            if let Some(hint) = error.hint() {
+                eprintln!("hint: {hint}");
+            }
+            ExitCode::from(error_exit_code(&error))
+        }
+    }
+}
+
+/// Exit code for each category of error. 1 is reserved for `--exit-code` and 2 is also used by
+/// clap for invalid arguments.
+const fn error_exit_code(error: &Error) -> u8 {
+    match error {
+        Error::InvalidConfig(_) => 2,
+        Error::GitNotInstalled { .. } => 3,
+        Error::NotARepository { .. } => 4,
+        Error::RefNotFound { .. } => 5,
+        Error::NoMergeBase { .. } | Error::ShallowHistory { .. } => 6,
+        Error::AuthenticationFailed { .. } => 7,
+        Error::DirtyWorkingTree { .. } => 8,
+        Error::Timeout { .. } => 9,
+        Error::OutputWrite { .. } | Error::UnsafePath(_) | Error::ExportFailed(_) => 10,
+        _ => 11,
+    }
+}
 
-    let changes = if let Some(output_dir) = &cli.output_dir {
-        if let Some(branch) = cli.branch {
-            if let Some(target_branch) = &cli.target_branch {
-                processor.export_branch_changes(&branch, target_branch, output_dir)?
-            } else {
-                processor.export_changes_from_default_branch(&branch, output_dir)?
+/// Runs the selected subcommand, or the diff when there is none
+fn run(cli: &Cli) -> git_changes::Result<ExitCode> {
+    match &cli.command {
+        Some(Command::Cache {
+            command:
+                CacheCommand::Prune {
+                    cache_dir,
+                    max_size,
+                },
+        }) => {
+            let report = cache::prune(cache_dir, *max_size)?;
+            println!(
+                "Removed {} mirror(s), freed {} bytes; the cache now uses {} bytes",
+                report.removed.len(),
+                report.freed_bytes,
+                report.remaining_bytes
+            );
+            for path in &report.removed {
+                println!("  - {}", path.display());
             }
-        } else if let Some(commit) = cli.commit {
-            processor.export_commit_changes(&commit, output_dir)?
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Check(args)) => {
+            let CheckArgs {
+                repo,
+                target,
+                policy: policy_path,
+                ignore,
+                format,
+            } = args.as_ref();
+            let policy = Policy::load(policy_path)?;
+            if policy.is_empty() {
+                return Err(Error::InvalidConfig(format!(
+                    "{}: the policy has no [[rule]] tables",
+                    policy_path.display()
+                )));
+            }
+            let changes = repo.processor(ignore)?.list(&target.diff_spec())?;
+            let violations = policy.evaluate(&changes);
+            match format {
+                CheckFormat::Text => print_violations(&violations, policy.len()),
+                CheckFormat::Json => {
+                    serde_json::to_writer_pretty(std::io::stdout().lock(), &violations)
+                        .map_err(std::io::Error::from)?;
+                    println!();
+                }
+                CheckFormat::Github => print_github_annotations(&violations),
+            }
+            let failed = violations
+                .iter()
+                .any(|violation| violation.severity == Severity::Error);
+            Ok(if failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            })
+        }
+        Some(Command::Owners(args)) => {
+            let changes = args
+                .repo
+                .processor(&args.ignore)?
+                .with_code_owners(true)
+                .list(&args.target.diff_spec())?;
+            print_owners(&changes, args.unowned, args.format)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Impact(args)) => Ok(if run_impact(args)? {
+            ExitCode::SUCCESS
         } else {
-            unreachable!("ArgGroup ensures exactly one target is provided")
+            ExitCode::FAILURE
+        }),
+        Some(Command::Changelog(args)) => {
+            let plan = args
+                .repo
+                .processor(&args.ignore)?
+                .release_plan(&args.target.diff_spec())?;
+            match args.format {
+                ChangelogFormat::Markdown => {
+                    print!("{}", plan.changelog(args.title.as_deref()));
+                    eprintln!("note: suggested version bump: {}", plan.bump);
+                    for package in plan.packages.values() {
+                        eprintln!("note:   {}: {}", package.name, package.bump);
+                    }
+                }
+                ChangelogFormat::Json => {
+                    serde_json::to_writer_pretty(std::io::stdout().lock(), &plan)
+                        .map_err(std::io::Error::from)?;
+                    println!();
+                }
+            }
+            Ok(ExitCode::SUCCESS)
         }
-    } else if let Some(branch) = cli.branch {
-        if let Some(target_branch) = &cli.target_branch {
-            processor.list_branch_changes(&branch, target_branch)?
+        Some(Command::ScanSecrets(args)) => Ok(if run_scan_secrets(args)? {
+            ExitCode::FAILURE
         } else {
-            processor.list_changes_from_default_branch(&branch)?
+            ExitCode::SUCCESS
+        }),
+        None => run_diff(cli),
+    }
+}
+
+/// Lists or exports the changes selected on the command line
+fn run_diff(cli: &Cli) -> git_changes::Result<ExitCode> {
+    let processor = cli.repo.processor(&cli.ignore)?;
+    let spec = cli.target.diff_spec();
+    let exit_code = |changed: bool| {
+        if cli.exit_code && changed {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        }
+    };
+    if cli.format == OutputFormat::Ndjson && cli.output_dir.is_none() {
+        let count = print_ndjson(processor.stream(&spec)?)?;
+        return Ok(exit_code(count > 0));
+    }
+
+    let changes = if let Some(output_dir) = &cli.output_dir {
+        let mut options = ExportOptions::new(output_dir);
+        if let Some(manifest) = &cli.manifest {
+            options = options.with_manifest(manifest);
         }
-    } else if let Some(commit) = cli.commit {
-        processor.list_commit_changes(&commit)?
+        processor.export(&spec, &options)?
     } else {
-        unreachable!("ArgGroup ensures exactly one target is provided")
+        processor.list(&spec)?
     };
 
-    print_changes_summary(&changes);
+    match cli.format {
+        OutputFormat::Text => print_changes_summary(&changes),
+        OutputFormat::Ndjson => {
+            print_ndjson(changes.iter().cloned().map(Ok))?;
+        }
+        OutputFormat::Json => {
+            let output = JsonOutput {
+                changes: &changes,
+                commits: processor.list_commits(&spec)?,
+            };
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &output)
+                .map_err(std::io::Error::from)?;
+            println!();
+        }
+    }
 
-    Ok(())
+    Ok(exit_code(!changes.is_empty()))
 }
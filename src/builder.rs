@@ -0,0 +1,345 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, instrument};
+
+use crate::cache;
+use crate::error::{Error, Result};
+use crate::filter::PathFilter;
+use crate::git::{redact_url, CloneOptions, Git, GitCli, GitConfig, Secret};
+use crate::processor::{CommitAttribution, GitChangesProcessor, DEFAULT_DEEPEN_LIMIT};
+
+/// Where the repository to analyze comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepoSource {
+    /// A repository on the local filesystem
+    Local(PathBuf),
+    /// A URL that is cloned into a temporary directory
+    Remote(String),
+}
+
+impl RepoSource {
+    /// Classifies a repository location the way `git clone` does: URLs with a scheme
+    /// (`https://`, `ssh://`, `git://`, `file://`, ...) and scp-like `[user@]host:path` are
+    /// remote, anything else is a local path.
+    #[must_use]
+    pub fn parse(repo: &str) -> Self {
+        if let Some((scheme, _)) = repo.split_once("://") {
+            let is_scheme = scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+            if !scheme.is_empty() && is_scheme {
+                return Self::Remote(repo.to_string());
+            }
+        }
+
+        // scp-like syntax needs a colon before any slash, e.g. `git@github.com:owner/repo.git`.
+        // A single letter before the colon is a Windows drive (`C:\repo`), not a host.
+        if let Some((host, _)) = repo.split_once(':') {
+            let is_drive = host.len() == 1 && host.chars().all(|c| c.is_ascii_alphabetic());
+            if !host.is_empty() && !host.contains(['/', '\\']) && !is_drive {
+                return Self::Remote(repo.to_string());
+            }
+        }
+
+        Self::Local(PathBuf::from(repo))
+    }
+}
+
+impl fmt::Display for RepoSource {
+    /// Shows the path or the URL, without any credentials embedded in the URL
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(path) => write!(f, "{}", path.display()),
+            Self::Remote(url) => f.write_str(&redact_url(url)),
+        }
+    }
+}
+
+/// Implementation used to run git operations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Spawns the `git` command line tool
+    #[default]
+    Cli,
+}
+
+/// Configures and creates a [`GitChangesProcessor`], see [`GitChangesProcessor::builder`]
+#[derive(Debug)]
+pub struct ProcessorBuilder {
+    source: Option<RepoSource>,
+    clone: CloneOptions,
+    git: GitConfig,
+    backend: Backend,
+    resolve_lfs: bool,
+    recurse_submodules: bool,
+    jobs: usize,
+    deepen_limit: u32,
+    cache_dir: Option<PathBuf>,
+    cache_max_size: Option<u64>,
+    path_filter: PathFilter,
+    code_owners: bool,
+    commit_attribution: Option<CommitAttribution>,
+}
+
+impl Default for ProcessorBuilder {
+    fn default() -> Self {
+        Self {
+            source: None,
+            clone: CloneOptions::default(),
+            git: GitConfig::default(),
+            backend: Backend::default(),
+            resolve_lfs: true,
+            recurse_submodules: false,
+            jobs: 1,
+            deepen_limit: DEFAULT_DEEPEN_LIMIT,
+            cache_dir: None,
+            cache_max_size: None,
+            path_filter: PathFilter::new(),
+            code_owners: false,
+            commit_attribution: None,
+        }
+    }
+}
+
+impl ProcessorBuilder {
+    /// Analyzes a repository on the local filesystem
+    #[must_use]
+    pub fn local(mut self, path: impl Into<PathBuf>) -> Self {
+        self.source = Some(RepoSource::Local(path.into()));
+        self
+    }
+
+    /// Analyzes a remote repository, cloned into a temporary directory
+    #[must_use]
+    pub fn remote(mut self, url: impl Into<String>) -> Self {
+        self.source = Some(RepoSource::Remote(url.into()));
+        self
+    }
+
+    /// Analyzes a local path or remote URL, classified with [`RepoSource::parse`]
+    #[must_use]
+    pub fn repo(mut self, repo: &str) -> Self {
+        self.source = Some(RepoSource::parse(repo));
+        self
+    }
+
+    /// Clones remote repositories shallowly, with this many commits per branch
+    #[must_use]
+    pub const fn clone_depth(mut self, depth: u32) -> Self {
+        self.clone.depth = Some(depth);
+        self
+    }
+
+    /// Makes a partial clone of remote repositories, e.g. `blob:none` (blobless) or `tree:0`
+    /// (treeless). Missing blobs are fetched in one batch for the files that are exported.
+    #[must_use]
+    pub fn clone_filter(mut self, filter: impl Into<String>) -> Self {
+        self.clone.filter = Some(filter.into());
+        self
+    }
+
+    /// Clones only the history of one branch: the one set with
+    /// [`ProcessorBuilder::clone_branch`], or the remote's default branch
+    #[must_use]
+    pub const fn single_branch(mut self, enabled: bool) -> Self {
+        self.clone.single_branch = enabled;
+        self
+    }
+
+    /// Checks out `branch` after cloning instead of the remote's default branch
+    #[must_use]
+    pub fn clone_branch(mut self, branch: impl Into<String>) -> Self {
+        self.clone.branch = Some(branch.into());
+        self
+    }
+
+    /// Clones without checking out any files. Branches are then compared without being
+    /// checked out, see [`GitChangesProcessor::with_branch_checkout`].
+    #[must_use]
+    pub const fn no_checkout(mut self, enabled: bool) -> Self {
+        self.clone.no_checkout = enabled;
+        self
+    }
+
+    /// Keeps a bare mirror of remote repositories in `dir`. Later runs only fetch new objects
+    /// into the mirror and clone with `--reference` to it, see [`crate::cache`].
+    #[must_use]
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Prunes the least recently used mirrors once the cache grows beyond `bytes`
+    #[must_use]
+    pub const fn cache_max_size(mut self, bytes: u64) -> Self {
+        self.cache_max_size = Some(bytes);
+        self
+    }
+
+    /// Authenticates to HTTPS remotes with a token (e.g. a personal access token). The token is
+    /// answered by a credential helper from the environment of the git processes, so it is never
+    /// part of a URL, a command line or a log.
+    #[must_use]
+    pub fn https_token(mut self, token: impl Into<String>) -> Self {
+        self.git.auth.https_token = Some(Secret::new(token.into()));
+        self
+    }
+
+    /// User name sent along with [`ProcessorBuilder::https_token`] (defaults to `x-access-token`,
+    /// which GitHub expects; GitLab accepts any name, e.g. `oauth2`)
+    #[must_use]
+    pub fn https_username(mut self, username: impl Into<String>) -> Self {
+        self.git.auth.https_username = Some(username.into());
+        self
+    }
+
+    /// Authenticates to SSH remotes with this private key, ignoring the keys of the SSH agent
+    #[must_use]
+    pub fn ssh_key(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git.auth.ssh_key = Some(path.into());
+        self
+    }
+
+    /// Verifies HTTPS remotes with the certificates in this bundle instead of the system ones
+    #[must_use]
+    pub fn ca_bundle(mut self, path: impl Into<PathBuf>) -> Self {
+        self.git.auth.ca_bundle = Some(path.into());
+        self
+    }
+
+    /// Runs this git executable instead of the `git` found on `PATH`
+    #[must_use]
+    pub fn git_binary(mut self, binary: impl Into<PathBuf>) -> Self {
+        self.git.binary = binary.into();
+        self
+    }
+
+    /// Sets an environment variable for every git process
+    #[must_use]
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.git
+            .env
+            .push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
+    /// Kills git commands (including the clone) that run longer than `timeout`.
+    /// Streaming reads, whose pace is set by the caller, are not limited.
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.git.timeout = Some(timeout);
+        self
+    }
+
+    /// Selects how git operations are performed (defaults to [`Backend::Cli`])
+    #[must_use]
+    pub const fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// See [`GitChangesProcessor::with_lfs_resolution`]
+    #[must_use]
+    pub const fn lfs_resolution(mut self, enabled: bool) -> Self {
+        self.resolve_lfs = enabled;
+        self
+    }
+
+    /// See [`GitChangesProcessor::with_submodule_recursion`]
+    #[must_use]
+    pub const fn submodule_recursion(mut self, enabled: bool) -> Self {
+        self.recurse_submodules = enabled;
+        self
+    }
+
+    /// See [`GitChangesProcessor::with_jobs`]
+    #[must_use]
+    pub const fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// See [`GitChangesProcessor::with_deepen_limit`]
+    #[must_use]
+    pub const fn deepen_limit(mut self, commits: u32) -> Self {
+        self.deepen_limit = commits;
+        self
+    }
+
+    /// See [`GitChangesProcessor::with_path_filter`]
+    #[must_use]
+    pub fn path_filter(mut self, filter: PathFilter) -> Self {
+        self.path_filter = filter;
+        self
+    }
+
+    /// See [`GitChangesProcessor::with_code_owners`]
+    #[must_use]
+    pub const fn code_owners(mut self, enabled: bool) -> Self {
+        self.code_owners = enabled;
+        self
+    }
+
+    /// See [`GitChangesProcessor::with_commit_attribution`]
+    #[must_use]
+    pub const fn commit_attribution(mut self, mode: Option<CommitAttribution>) -> Self {
+        self.commit_attribution = mode;
+        self
+    }
+
+    /// Creates the processor, cloning the repository first when it is remote
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no repository was set or the remote repository cannot be cloned
+    #[instrument(skip(self), fields(source = ?self.source.as_ref().map(ToString::to_string), backend = ?self.backend))]
+    pub fn build(self) -> Result<GitChangesProcessor> {
+        let Backend::Cli = self.backend;
+        let checkout_branches = !self.clone.no_checkout;
+        let git = match self.source {
+            None => {
+                return Err(Error::InvalidConfig(
+                    "no repository set, call `local`, `remote` or `repo`".to_string(),
+                ))
+            }
+            Some(RepoSource::Local(path)) => {
+                debug!(path = %path.display(), "Using local repository");
+                GitCli::with_config(path, self.git)
+            }
+            Some(RepoSource::Remote(url)) => {
+                debug!("Cloning remote repository");
+                let git = GitCli::new_with_temp_workspace(self.git)?;
+                let mut clone = self.clone;
+                let mut cache_lock = None;
+                if let Some(cache_dir) = &self.cache_dir {
+                    let mirror = cache::update_mirror(&git, cache_dir, &url)?;
+                    clone.reference = Some(mirror.path);
+                    cache_lock = Some(mirror.lock);
+                    if let Some(max_size) = self.cache_max_size {
+                        let report = cache::prune(cache_dir, max_size)?;
+                        debug!(removed = report.removed.len(), "Pruned clone cache");
+                    }
+                }
+                git.clone_repo(&url, &clone)?;
+                debug!("Successfully cloned remote repository");
+                match cache_lock {
+                    Some(lock) => git.with_cache_lock(lock),
+                    None => git,
+                }
+            }
+        };
+
+        Ok(GitChangesProcessor::from_git(git)
+            .with_lfs_resolution(self.resolve_lfs)
+            .with_submodule_recursion(self.recurse_submodules)
+            .with_jobs(self.jobs)
+            .with_deepen_limit(self.deepen_limit)
+            .with_branch_checkout(checkout_branches)
+            .with_path_filter(self.path_filter)
+            .with_code_owners(self.code_owners)
+            .with_commit_attribution(self.commit_attribution))
+    }
+}
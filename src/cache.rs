@@ -0,0 +1,151 @@
+//! Persistent cache of remote repositories.
+//!
+//! The cache directory holds one bare mirror per remote URL (`<hash>.git`) next to two lock
+//! files. `<hash>.lock` marks the mirror as in use: a processor holds a shared lock on it for as
+//! long as it lives, since its clone borrows objects from the mirror (`--reference`), and
+//! [`prune`] only removes mirrors it can lock exclusively. `<hash>.update` is held exclusively
+//! while the mirror is fetched, and released right after, so processors using the same mirror
+//! run side by side. Lock files are never removed, so every process locks the same file.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, instrument};
+
+use crate::error::Result;
+use crate::git::{CloneOptions, Git, GitCli};
+
+/// Outcome of [`prune`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Mirrors that were removed, least recently used first
+    pub removed: Vec<PathBuf>,
+    /// Bytes freed by removing them
+    pub freed_bytes: u64,
+    /// Bytes still used by the cache
+    pub remaining_bytes: u64,
+}
+
+/// A mirror in the cache, with the lock that keeps it from being pruned
+pub(crate) struct Mirror {
+    pub path: PathBuf,
+    pub lock: File,
+}
+
+/// Creates or updates the mirror of `url` in `cache_dir`, using the settings of `git`.
+/// The mirror is returned with a shared lock held.
+#[instrument(skip(git, url), fields(cache_dir = %cache_dir.display()))]
+pub(crate) fn update_mirror(git: &GitCli, cache_dir: &Path, url: &str) -> Result<Mirror> {
+    std::fs::create_dir_all(cache_dir)?;
+    let name = format!("{:016x}", fnv1a(url.trim_end_matches('/').as_bytes()));
+    let path = cache_dir.join(format!("{name}.git"));
+    // Taken first, so a prune cannot remove the mirror between its update and its use
+    let lock = open_lock(&cache_dir.join(format!("{name}.lock")))?;
+    lock.lock_shared()?;
+
+    let update_lock = open_lock(&cache_dir.join(format!("{name}.update")))?;
+    update_lock.lock()?;
+    let mirror_git = git.for_path(path.clone());
+    if path.join("HEAD").is_file() {
+        debug!(mirror = %path.display(), "Updating cached mirror");
+        mirror_git.fetch_mirror(url)?;
+    } else {
+        debug!(mirror = %path.display(), "Creating cached mirror");
+        if path.exists() {
+            // Left over by an interrupted clone
+            std::fs::remove_dir_all(&path)?;
+        }
+        let options = CloneOptions {
+            mirror: true,
+            ..CloneOptions::default()
+        };
+        mirror_git.clone_repo(url, &options)?;
+    }
+    // Records when the mirror was last used, for pruning
+    lock.set_modified(SystemTime::now())?;
+    drop(update_lock);
+
+    Ok(Mirror { path, lock })
+}
+
+/// Removes the least recently used mirrors from `cache_dir` until it uses at most `max_bytes`.
+/// Mirrors that are in use by a running processor are left alone.
+///
+/// # Errors
+///
+/// Returns an error if the cache directory cannot be read or a mirror cannot be removed
+#[instrument(fields(cache_dir = %cache_dir.display()))]
+pub fn prune(cache_dir: &Path, max_bytes: u64) -> Result<PruneReport> {
+    let mut mirrors = Vec::new();
+    if cache_dir.is_dir() {
+        for entry in std::fs::read_dir(cache_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "git") && path.is_dir() {
+                let lock_path = path.with_extension("lock");
+                let last_used = std::fs::metadata(&lock_path)
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                mirrors.push((last_used, dir_size(&path)?, path, lock_path));
+            }
+        }
+    }
+    mirrors.sort();
+
+    let mut report = PruneReport {
+        remaining_bytes: mirrors.iter().map(|(_, size, _, _)| size).sum(),
+        ..PruneReport::default()
+    };
+    for (_, size, path, lock_path) in mirrors {
+        if report.remaining_bytes <= max_bytes {
+            break;
+        }
+        let lock = open_lock(&lock_path)?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                debug!(mirror = %path.display(), "Mirror is in use, skipping");
+                continue;
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        debug!(mirror = %path.display(), size, "Removing cached mirror");
+        // The lock files stay: removing one that other processes have open would let two of
+        // them lock different files under the same name
+        std::fs::remove_dir_all(&path)?;
+        report.freed_bytes += size;
+        report.remaining_bytes -= size;
+        report.removed.push(path);
+    }
+    Ok(report)
+}
+
+/// Opens (creating if needed) the lock file at `path`
+fn open_lock(path: &Path) -> Result<File> {
+    Ok(OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?)
+}
+
+/// Total size of the files under `path`, without following symlinks
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// 64-bit FNV-1a, a stable hash for naming mirrors
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,297 @@
+use std::collections::btree_map;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Index;
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+use crate::{FileChange, FileStatus};
+
+/// Number of changes per status in a [`ChangeSet`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChangeStats {
+    pub total: usize,
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub type_changed: usize,
+}
+
+/// Order in which [`ChangeSet::sorted`] returns changes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By path (the natural order of a `ChangeSet`)
+    #[default]
+    Path,
+    /// By status (added, modified, deleted, type changed), then by path
+    StatusThenPath,
+}
+
+/// An ordered collection of file changes keyed by path.
+///
+/// Iteration and serialization always follow path order, so results are identical between
+/// runs. Serializes as a list of changes.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSet {
+    changes: BTreeMap<String, FileChange>,
+}
+
+impl ChangeSet {
+    /// Creates an empty change set
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// Number of changed paths
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns true when nothing changed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the change for `path`, if any
+    #[must_use]
+    pub fn get(&self, path: &str) -> Option<&FileChange> {
+        self.changes.get(path)
+    }
+
+    /// Returns a mutable reference to the change for `path`, if any
+    pub fn get_mut(&mut self, path: &str) -> Option<&mut FileChange> {
+        self.changes.get_mut(path)
+    }
+
+    /// Returns true when `path` changed
+    #[must_use]
+    pub fn contains(&self, path: &str) -> bool {
+        self.changes.contains_key(path)
+    }
+
+    /// Adds a change, replacing and returning any previous change for the same path
+    pub fn insert(&mut self, change: FileChange) -> Option<FileChange> {
+        self.changes.insert(change.path.clone(), change)
+    }
+
+    /// Removes and returns the change for `path`, if any
+    pub fn remove(&mut self, path: &str) -> Option<FileChange> {
+        self.changes.remove(path)
+    }
+
+    /// Iterates over the changes in path order
+    #[must_use = "iterators are lazy"]
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &FileChange> + ExactSizeIterator {
+        self.changes.values()
+    }
+
+    /// Iterates mutably over the changes in path order
+    pub fn iter_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut FileChange> + ExactSizeIterator {
+        self.changes.values_mut()
+    }
+
+    /// Iterates over the changed paths in order
+    #[must_use = "iterators are lazy"]
+    pub fn paths(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.changes.keys().map(String::as_str)
+    }
+
+    /// Iterates over the added files
+    pub fn added(&self) -> impl Iterator<Item = &FileChange> {
+        self.with_status(FileStatus::Added)
+    }
+
+    /// Iterates over the modified files
+    pub fn modified(&self) -> impl Iterator<Item = &FileChange> {
+        self.with_status(FileStatus::Modified)
+    }
+
+    /// Iterates over the deleted files
+    pub fn deleted(&self) -> impl Iterator<Item = &FileChange> {
+        self.with_status(FileStatus::Deleted)
+    }
+
+    /// Iterates over the files whose type changed (e.g. a file replaced by a symlink)
+    pub fn type_changed(&self) -> impl Iterator<Item = &FileChange> {
+        self.with_status(FileStatus::TypeChanged)
+    }
+
+    fn with_status(&self, status: FileStatus) -> impl Iterator<Item = &FileChange> {
+        self.iter().filter(move |change| change.status == status)
+    }
+
+    /// Iterates over the changes located under directory `dir` (e.g. `src` or `src/`)
+    pub fn paths_under<'a>(&'a self, dir: &str) -> impl Iterator<Item = &'a FileChange> {
+        let prefix = format!("{}/", dir.trim_end_matches('/'));
+        self.changes
+            .range(prefix.clone()..)
+            .take_while(move |(path, _)| path.starts_with(&prefix))
+            .map(|(_, change)| change)
+    }
+
+    /// Returns the changes matching `predicate`
+    #[must_use]
+    pub fn filter(&self, mut predicate: impl FnMut(&FileChange) -> bool) -> Self {
+        self.iter()
+            .filter(|change| predicate(change))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the changes in either set. For paths in both, the change from `self` is kept.
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        let mut union = other.clone();
+        union.extend(self.iter().cloned());
+        union
+    }
+
+    /// Returns the changes of `self` whose path also changed in `other`
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.filter(|change| other.contains(&change.path))
+    }
+
+    /// Returns the changes of `self` whose path did not change in `other`
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        self.filter(|change| !other.contains(&change.path))
+    }
+
+    /// Counts the changes per status
+    #[must_use]
+    pub fn stats(&self) -> ChangeStats {
+        let mut stats = ChangeStats {
+            total: self.len(),
+            ..ChangeStats::default()
+        };
+        for change in self {
+            match change.status {
+                FileStatus::Added => stats.added += 1,
+                FileStatus::Modified => stats.modified += 1,
+                FileStatus::Deleted => stats.deleted += 1,
+                FileStatus::TypeChanged => stats.type_changed += 1,
+            }
+        }
+        stats
+    }
+
+    /// Returns the distinct directories that directly contain a changed path, in order.
+    /// Files at the repository root do not contribute a directory.
+    #[must_use]
+    pub fn directories(&self) -> BTreeSet<String> {
+        self.paths()
+            .filter_map(|path| path.rsplit_once('/').map(|(dir, _)| dir.to_string()))
+            .collect()
+    }
+
+    /// Groups the changes by owner (user, team or email), see
+    /// [`crate::processor::GitChangesProcessor::with_code_owners`]. A change with several owners
+    /// appears in the group of each of them.
+    #[must_use]
+    pub fn group_by_owner(&self) -> BTreeMap<String, Self> {
+        let mut groups: BTreeMap<String, Self> = BTreeMap::new();
+        for change in self {
+            for owner in change.owners.iter().flatten() {
+                groups
+                    .entry(owner.clone())
+                    .or_default()
+                    .insert(change.clone());
+            }
+        }
+        groups
+    }
+
+    /// Iterates over the changes whose owners were looked up and that have none
+    pub fn unowned(&self) -> impl Iterator<Item = &FileChange> {
+        self.iter()
+            .filter(|change| change.owners.as_ref().is_some_and(Vec::is_empty))
+    }
+
+    /// Iterates over the Git LFS pointers that were exported as pointers, because their object
+    /// was missing from the local LFS store or did not match the pointer
+    pub fn unresolved_lfs(&self) -> impl Iterator<Item = &FileChange> {
+        self.iter().filter(|change| {
+            change
+                .lfs_pointer
+                .as_ref()
+                .is_some_and(|pointer| !pointer.resolved)
+        })
+    }
+
+    /// Returns the changes in the requested order. Sorting is stable and fully deterministic.
+    #[must_use]
+    pub fn sorted(&self, order: SortOrder) -> Vec<&FileChange> {
+        let mut changes: Vec<&FileChange> = self.iter().collect();
+        if order == SortOrder::StatusThenPath {
+            changes.sort_by(|a, b| a.status.cmp(&b.status).then_with(|| a.path.cmp(&b.path)));
+        }
+        changes
+    }
+}
+
+impl Index<&str> for ChangeSet {
+    type Output = FileChange;
+
+    fn index(&self, path: &str) -> &Self::Output {
+        self.get(path)
+            .unwrap_or_else(|| panic!("no change for path {path:?}"))
+    }
+}
+
+impl FromIterator<FileChange> for ChangeSet {
+    fn from_iter<I: IntoIterator<Item = FileChange>>(iter: I) -> Self {
+        let mut change_set = Self::new();
+        change_set.extend(iter);
+        change_set
+    }
+}
+
+impl Extend<FileChange> for ChangeSet {
+    fn extend<I: IntoIterator<Item = FileChange>>(&mut self, iter: I) {
+        for change in iter {
+            self.insert(change);
+        }
+    }
+}
+
+impl IntoIterator for ChangeSet {
+    type Item = FileChange;
+    type IntoIter = btree_map::IntoValues<String, FileChange>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_values()
+    }
+}
+
+impl<'a> IntoIterator for &'a ChangeSet {
+    type Item = &'a FileChange;
+    type IntoIter = btree_map::Values<'a, String, FileChange>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.values()
+    }
+}
+
+impl Serialize for ChangeSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.changes.len()))?;
+        for change in self.changes.values() {
+            seq.serialize_element(change)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ChangeSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<FileChange>::deserialize(deserializer).map(Self::from_iter)
+    }
+}
@@ -0,0 +1,337 @@
+//! Conventional Commits (<https://www.conventionalcommits.org>): the semantic version bump they
+//! call for, and a changelog grouped by type.
+//!
+//! A header reads `type(scope)!: description`, where the scope and `!` are optional. `!` or a
+//! `BREAKING CHANGE:` footer marks a breaking change, which calls for a major release; `feat`
+//! calls for a minor release, and `fix` and `perf` for a patch release.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Commit;
+
+/// Footers that describe a breaking change
+const BREAKING_FOOTERS: [&str; 2] = ["BREAKING CHANGE:", "BREAKING-CHANGE:"];
+
+/// Changelog sections, in order: commit type and heading. Other types, and commits that do not
+/// follow Conventional Commits, are listed last under "Other Changes".
+const SECTIONS: [(&str, &str); 11] = [
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance Improvements"),
+    ("revert", "Reverts"),
+    ("refactor", "Code Refactoring"),
+    ("docs", "Documentation"),
+    ("style", "Styles"),
+    ("test", "Tests"),
+    ("build", "Build System"),
+    ("ci", "Continuous Integration"),
+    ("chore", "Chores"),
+];
+
+/// Semantic version bump, ordered from none to major
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionBump {
+    #[default]
+    None,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl fmt::Display for VersionBump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Patch => "patch",
+            Self::Minor => "minor",
+            Self::Major => "major",
+        })
+    }
+}
+
+/// A commit whose message follows Conventional Commits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConventionalCommit {
+    pub sha: String,
+    /// Commit type, e.g. `feat` or `fix`, in lower case
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+    /// Text of the `BREAKING CHANGE:` footer, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breaking_change: Option<String>,
+    /// Paths the commit changed, see [`Commit::paths`]
+    pub paths: Vec<String>,
+}
+
+impl ConventionalCommit {
+    /// Parses the message of `commit`, or returns `None` when its subject is not a
+    /// Conventional Commits header
+    #[must_use]
+    pub fn parse(commit: &Commit) -> Option<Self> {
+        let (header, description) = commit.subject.split_once(':')?;
+        let description = description.trim();
+        let (header, bang) = header
+            .strip_suffix('!')
+            .map_or((header, false), |header| (header, true));
+        let (kind, scope) = match header.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?.trim())),
+            None => (header, None),
+        };
+        let valid_kind = !kind.is_empty()
+            && kind
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_kind || description.is_empty() {
+            return None;
+        }
+
+        let breaking_change = breaking_footer(&commit.body);
+        Some(Self {
+            sha: commit.sha.clone(),
+            kind: kind.to_ascii_lowercase(),
+            scope: scope.filter(|scope| !scope.is_empty()).map(str::to_string),
+            breaking: bang || breaking_change.is_some(),
+            description: description.to_string(),
+            breaking_change,
+            paths: commit.paths.clone(),
+        })
+    }
+
+    /// The version bump this commit calls for
+    #[must_use]
+    pub fn bump(&self) -> VersionBump {
+        if self.breaking {
+            return VersionBump::Major;
+        }
+        match self.kind.as_str() {
+            "feat" => VersionBump::Minor,
+            "fix" | "perf" => VersionBump::Patch,
+            _ => VersionBump::None,
+        }
+    }
+}
+
+/// A package of a workspace: a directory with a `Cargo.toml` or `package.json` that names it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    /// Directory of the package, relative to the repository root (empty for the root)
+    pub path: String,
+}
+
+impl Package {
+    /// Reads the package name from the content of its manifest, `manifest_path`. Returns `None`
+    /// for other files and for manifests that do not name a package (e.g. a virtual workspace).
+    #[must_use]
+    pub fn from_manifest(manifest_path: &str, content: &str) -> Option<Self> {
+        let (path, file) = manifest_path
+            .rsplit_once('/')
+            .unwrap_or(("", manifest_path));
+        let name = match file {
+            "Cargo.toml" => {
+                let manifest: toml::Table = toml::from_str(content).ok()?;
+                manifest.get("package")?.get("name")?.as_str()?.to_string()
+            }
+            "package.json" => {
+                let manifest: serde_json::Value = serde_json::from_str(content).ok()?;
+                manifest.get("name")?.as_str()?.to_string()
+            }
+            _ => return None,
+        };
+        Some(Self {
+            name,
+            path: path.to_string(),
+        })
+    }
+
+    /// Returns true when `path` is inside the package directory
+    #[must_use]
+    pub fn contains(&self, path: &str) -> bool {
+        self.path.is_empty()
+            || path
+                .strip_prefix(&self.path)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// The bump a package needs, and the commits that changed it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageBump {
+    pub name: String,
+    pub path: String,
+    pub bump: VersionBump,
+    /// Commits that changed files of the package
+    pub commits: Vec<String>,
+}
+
+/// Suggested release for the commits of a comparison
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleasePlan {
+    /// Highest bump over all commits
+    pub bump: VersionBump,
+    /// Packages changed by the commits, by name. A file belongs to the innermost package that
+    /// contains it.
+    pub packages: BTreeMap<String, PackageBump>,
+    /// Commits following Conventional Commits, children before parents
+    pub commits: Vec<ConventionalCommit>,
+    /// Commits that do not follow Conventional Commits (merges excluded), which do not affect
+    /// the bump
+    pub other: Vec<Commit>,
+}
+
+impl ReleasePlan {
+    /// Parses `commits` and suggests the bump overall and for each of `packages`
+    #[must_use]
+    pub fn new(commits: &[Commit], packages: &[Package]) -> Self {
+        let mut plan = Self::default();
+        for commit in commits.iter().filter(|commit| commit.parents.len() < 2) {
+            let Some(conventional) = ConventionalCommit::parse(commit) else {
+                plan.other.push(commit.clone());
+                continue;
+            };
+            let bump = conventional.bump();
+            plan.bump = plan.bump.max(bump);
+            for package in conventional
+                .paths
+                .iter()
+                .filter_map(|path| innermost_package(packages, path))
+            {
+                let entry = plan
+                    .packages
+                    .entry(package.name.clone())
+                    .or_insert_with(|| PackageBump {
+                        name: package.name.clone(),
+                        path: package.path.clone(),
+                        bump: VersionBump::None,
+                        commits: Vec::new(),
+                    });
+                entry.bump = entry.bump.max(bump);
+                if entry.commits.last() != Some(&conventional.sha) {
+                    entry.commits.push(conventional.sha.clone());
+                }
+            }
+            plan.commits.push(conventional);
+        }
+        plan
+    }
+
+    /// Renders the changelog as Markdown, under a `## {title}` heading when `title` is set.
+    /// Breaking changes come first, then one section per commit type.
+    #[must_use]
+    pub fn changelog(&self, title: Option<&str>) -> String {
+        let mut markdown = String::new();
+        if let Some(title) = title {
+            let _ = writeln!(markdown, "## {title}\n");
+        }
+
+        let breaking: Vec<_> = self.commits.iter().filter(|c| c.breaking).collect();
+        if !breaking.is_empty() {
+            markdown.push_str("### ⚠ BREAKING CHANGES\n\n");
+            for commit in breaking {
+                let text = commit
+                    .breaking_change
+                    .as_deref()
+                    .unwrap_or(&commit.description);
+                push_entry(&mut markdown, commit.scope.as_deref(), text, &commit.sha);
+            }
+            markdown.push('\n');
+        }
+
+        let mut sections: Vec<Vec<&ConventionalCommit>> = vec![Vec::new(); SECTIONS.len()];
+        let mut other = Vec::new();
+        for commit in &self.commits {
+            match SECTIONS.iter().position(|(kind, _)| *kind == commit.kind) {
+                Some(index) => sections[index].push(commit),
+                None => other.push(commit),
+            }
+        }
+        for ((_, heading), commits) in SECTIONS.iter().zip(sections) {
+            if commits.is_empty() {
+                continue;
+            }
+            let _ = writeln!(markdown, "### {heading}\n");
+            for commit in commits {
+                push_entry(
+                    &mut markdown,
+                    commit.scope.as_deref(),
+                    &commit.description,
+                    &commit.sha,
+                );
+            }
+            markdown.push('\n');
+        }
+        if !other.is_empty() || !self.other.is_empty() {
+            markdown.push_str("### Other Changes\n\n");
+            for commit in other {
+                let text = format!("{}: {}", commit.kind, commit.description);
+                push_entry(&mut markdown, commit.scope.as_deref(), &text, &commit.sha);
+            }
+            for commit in &self.other {
+                push_entry(&mut markdown, None, &commit.subject, &commit.sha);
+            }
+            markdown.push('\n');
+        }
+
+        if markdown.ends_with("\n\n") {
+            markdown.pop();
+        }
+        markdown
+    }
+}
+
+/// The innermost of `packages` that contains `path`
+fn innermost_package<'a>(packages: &'a [Package], path: &str) -> Option<&'a Package> {
+    packages
+        .iter()
+        .filter(|package| package.contains(path))
+        .max_by_key(|package| package.path.len())
+}
+
+/// Text of the first `BREAKING CHANGE:` footer in `body`, with its continuation lines
+fn breaking_footer(body: &str) -> Option<String> {
+    let mut lines = body.lines();
+    let first = lines.by_ref().find_map(|line| {
+        BREAKING_FOOTERS
+            .iter()
+            .find_map(|footer| line.strip_prefix(footer))
+    })?;
+    let mut text = first.trim().to_string();
+    for line in lines.take_while(|line| !line.trim().is_empty() && !is_footer(line)) {
+        text.push(' ');
+        text.push_str(line.trim());
+    }
+    Some(text)
+}
+
+/// Returns true when `line` starts a footer, e.g. `Refs: #12` or `Fixes #3`
+fn is_footer(line: &str) -> bool {
+    let token_end = line.find([':', ' ']).unwrap_or(line.len());
+    let token = &line[..token_end];
+    let separator = &line[token_end..];
+    !token.is_empty()
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && (separator.starts_with(": ") || separator.starts_with(" #"))
+}
+
+/// Appends a changelog list item with the short commit id
+fn push_entry(markdown: &mut String, scope: Option<&str>, text: &str, sha: &str) {
+    let short = &sha[..sha.len().min(7)];
+    match scope {
+        Some(scope) => {
+            let _ = writeln!(markdown, "- **{scope}:** {text} ({short})");
+        }
+        None => {
+            let _ = writeln!(markdown, "- {text} ({short})");
+        }
+    }
+}
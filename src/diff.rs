@@ -0,0 +1,257 @@
+use crate::error::{Error, Result};
+use crate::git::GitOutputStream;
+use crate::{EntryKind, FileChange, FileStatus, SubmoduleChange};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Tree entry mode git uses for submodules
+const GITLINK_MODE: &str = "160000";
+
+/// Mode git prints for the missing side of an added or deleted entry
+const NULL_MODE: &str = "000000";
+
+/// Object id git prints for the missing side of an added or deleted entry
+const NULL_OID: &str = "0000000000000000000000000000000000000000";
+
+/// Hash of the empty tree, used to diff a newly added submodule from nothing
+pub const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// Arguments shared by every diff listing. `-z` keeps paths verbatim (no quoting or escaping)
+/// and separates every field with NUL, so paths may contain spaces, tabs or newlines.
+pub const RAW_DIFF_ARGS: [&str; 5] = ["diff", "--raw", "-z", "--no-abbrev", "--no-renames"];
+
+/// Arguments for a patch diff whose output can be split per file. Prefixes, colors and external
+/// diff drivers are pinned so user configuration cannot change the headers.
+pub const PATCH_DIFF_ARGS: [&str; 8] = [
+    "-c",
+    "core.quotePath=false",
+    "diff",
+    "--no-color",
+    "--no-ext-diff",
+    "--no-renames",
+    "--src-prefix=a/",
+    "--dst-prefix=b/",
+];
+
+/// Streams the entries of a running `git diff --raw -z --no-abbrev --no-renames`.
+///
+/// Each entry is a `:100644 100644 <old-oid> <new-oid> M` header followed by the path, both
+/// terminated by NUL, so entries are parsed as soon as they are read.
+pub struct RawDiffReader {
+    stream: Option<GitOutputStream>,
+}
+
+impl RawDiffReader {
+    #[must_use]
+    pub const fn new(stream: GitOutputStream) -> Self {
+        Self {
+            stream: Some(stream),
+        }
+    }
+
+    fn next_entry(stream: &mut GitOutputStream) -> Result<Option<FileChange>> {
+        loop {
+            let Some(meta) = stream.read_field(0)? else {
+                return Ok(None);
+            };
+            if !meta.starts_with(b":") {
+                continue;
+            }
+            let Some(path) = stream.read_field(0)? else {
+                return Ok(None);
+            };
+            let meta = String::from_utf8_lossy(&meta);
+            let path = String::from_utf8(path).map_err(|e| Error::InvalidUtf8 {
+                path: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+            })?;
+            if let Some(change) = parse_raw_entry(&meta, &path) {
+                return Ok(Some(change));
+            }
+        }
+    }
+}
+
+impl Iterator for RawDiffReader {
+    type Item = Result<FileChange>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stream = self.stream.as_mut()?;
+        match Self::next_entry(stream) {
+            Ok(Some(change)) => Some(Ok(change)),
+            Ok(None) => self.stream.take()?.finish().err().map(Err),
+            Err(e) => {
+                self.stream = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Parses a single `--raw` entry from its header and path
+#[must_use]
+pub fn parse_raw_entry(meta: &str, path_str: &str) -> Option<FileChange> {
+    let parts: Vec<&str> = meta.trim_start_matches(':').split_whitespace().collect();
+    if parts.len() < 5 {
+        return None;
+    }
+    let (old_mode, new_mode, old_oid, new_oid, status_str) =
+        (parts[0], parts[1], parts[2], parts[3], parts[4]);
+    let old_object = (old_oid != NULL_OID).then(|| old_oid.to_string());
+    let new_object = (new_oid != NULL_OID).then(|| new_oid.to_string());
+
+    let file_status = match status_str {
+        "A" => FileStatus::Added,
+        "D" => FileStatus::Deleted,
+        "T" => FileStatus::TypeChanged,
+        _ => FileStatus::Modified,
+    };
+
+    let old_mode = (old_mode != NULL_MODE).then(|| old_mode.to_string());
+    let new_mode = (new_mode != NULL_MODE).then(|| new_mode.to_string());
+    let entry_kind = new_mode
+        .as_deref()
+        .or(old_mode.as_deref())
+        .and_then(EntryKind::from_mode)
+        .unwrap_or(EntryKind::File);
+
+    let is_gitlink = |mode: &Option<String>| mode.as_deref() == Some(GITLINK_MODE);
+    let submodule = (is_gitlink(&old_mode) || is_gitlink(&new_mode)).then(|| SubmoduleChange {
+        old_commit: old_object.clone().filter(|_| is_gitlink(&old_mode)),
+        new_commit: new_object.clone().filter(|_| is_gitlink(&new_mode)),
+        changes: Vec::new(),
+    });
+
+    debug!(
+        file_path = %path_str,
+        status = ?file_status,
+        submodule = submodule.is_some(),
+        "Processing file change from diff"
+    );
+
+    Some(FileChange {
+        path: path_str.to_string(),
+        status: file_status,
+        old_mode,
+        new_mode,
+        entry_kind,
+        old_oid: old_object,
+        new_oid: new_object,
+        lfs_pointer: None,
+        submodule,
+        owners: None,
+        commits: None,
+    })
+}
+
+/// Splits the output of a patch diff (see [`PATCH_DIFF_ARGS`]) into per-file patches keyed by
+/// path. Patches for the same path (e.g. the delete and add halves of a type change) are joined.
+#[must_use]
+pub fn split_patch(output: &[u8]) -> HashMap<String, Vec<u8>> {
+    let mut patches: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in output.split_inclusive(|byte| *byte == b'\n') {
+        if let Some(header) = line.strip_prefix(b"diff --git ") {
+            current = parse_header_path(header);
+            if current.is_none() {
+                debug!(header = %String::from_utf8_lossy(line).trim_end(), "Unrecognized diff header");
+            }
+        }
+        if let Some(path) = &current {
+            patches
+                .entry(path.clone())
+                .or_default()
+                .extend_from_slice(line);
+        }
+    }
+    patches
+}
+
+/// Returns the lines a per-file patch adds, with their line numbers in the new file
+#[must_use]
+pub fn added_lines(patch: &[u8]) -> Vec<(usize, String)> {
+    let mut added = Vec::new();
+    let mut next_line: Option<usize> = None;
+
+    for line in patch.split(|byte| *byte == b'\n') {
+        if line.starts_with(b"diff --git ") {
+            next_line = None;
+        } else if let Some(hunk) = line.strip_prefix(b"@@ ") {
+            next_line = parse_hunk_start(hunk);
+        } else if let Some(number) = next_line.as_mut() {
+            match line.first() {
+                Some(b'+') => {
+                    let text = &line[1..];
+                    let text = text.strip_suffix(b"\r").unwrap_or(text);
+                    added.push((*number, String::from_utf8_lossy(text).into_owned()));
+                    *number += 1;
+                }
+                Some(b' ') => *number += 1,
+                _ => {}
+            }
+        }
+    }
+    added
+}
+
+/// Reads the first new-file line of a `-a,b +c,d @@` hunk header
+fn parse_hunk_start(hunk: &[u8]) -> Option<usize> {
+    let hunk = std::str::from_utf8(hunk).ok()?;
+    let new_range = hunk.split(' ').find_map(|range| range.strip_prefix('+'))?;
+    new_range.split(',').next()?.parse().ok()
+}
+
+/// Extracts the path from the `a/<path> b/<path>` part of a `diff --git` header. Without renames
+/// both sides name the same path, which makes unquoted headers unambiguous even with spaces.
+fn parse_header_path(header: &[u8]) -> Option<String> {
+    let header = header.strip_suffix(b"\n").unwrap_or(header);
+    let path = if header.first() == Some(&b'"') {
+        unquote_c_style(header)?
+    } else {
+        if header.len() < 5 || !(header.len() - 5).is_multiple_of(2) {
+            return None;
+        }
+        // `a/` + path + ` ` + `b/` + path
+        let path_len = (header.len() - 5) / 2;
+        let (old_side, rest) = header.split_at(path_len + 2);
+        let new_side = rest.strip_prefix(b" ")?;
+        if !new_side.starts_with(b"b/") || new_side[2..] != old_side[2..] {
+            return None;
+        }
+        old_side.to_vec()
+    };
+    let path = path.strip_prefix(b"a/")?;
+    Some(String::from_utf8_lossy(path).into_owned())
+}
+
+/// Decodes the leading C-style quoted string git uses for paths with special characters
+fn unquote_c_style(input: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = input.strip_prefix(b"\"")?.iter();
+    let mut result = Vec::new();
+    loop {
+        match *bytes.next()? {
+            b'"' => return Some(result),
+            b'\\' => {
+                let escaped = *bytes.next()?;
+                let decoded = match escaped {
+                    b'a' => 0x07,
+                    b'b' => 0x08,
+                    b't' => b'\t',
+                    b'n' => b'\n',
+                    b'v' => 0x0b,
+                    b'f' => 0x0c,
+                    b'r' => b'\r',
+                    b'0'..=b'3' => {
+                        let high = escaped - b'0';
+                        let mid = bytes.next()?.checked_sub(b'0').filter(|d| *d < 8)?;
+                        let low = bytes.next()?.checked_sub(b'0').filter(|d| *d < 8)?;
+                        (high << 6) | (mid << 3) | low
+                    }
+                    other => other,
+                };
+                result.push(decoded);
+            }
+            byte => result.push(byte),
+        }
+    }
+}
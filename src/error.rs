@@ -1,4 +1,26 @@
 use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Fragments of the messages git and its transports print when credentials are missing or wrong
+const AUTH_MESSAGES: [&str; 6] = [
+    "authentication failed",
+    "could not read username",
+    "could not read password",
+    "terminal prompts disabled",
+    "permission denied (publickey",
+    "http basic: access denied",
+];
+
+/// Fragments of the messages git prints when a fetched ref or object does not exist on the remote,
+/// or there is no remote to fetch it from
+const MISSING_REMOTE_OBJECT_MESSAGES: [&str; 5] = [
+    "not our ref",
+    "unadvertised object",
+    "unadvertized object",
+    "couldn't find remote ref",
+    "'origin' does not appear to be a git repository",
+];
 
 /// Custom error type for the library
 #[derive(Debug, thiserror::Error)]
@@ -6,11 +28,181 @@ pub enum Error {
     #[error("IO error: {0}")]
     IoError(#[from] io::Error),
 
+    /// A git command failed for a reason that is not classified below
     #[error("Git command failed: {0}")]
     GitCommandError(String),
 
+    #[error("Git executable not found: {}", .binary.display())]
+    GitNotInstalled { binary: PathBuf },
+
+    #[error("Not a git repository: {}", .path.display())]
+    NotARepository { path: PathBuf },
+
+    #[error("Reference not found: {reference}")]
+    RefNotFound { reference: String },
+
+    #[error("No merge base between {base} and {head}")]
+    NoMergeBase { base: String, head: String },
+
+    #[error("Could not find {what} after fetching {depth} more commits of shallow history")]
+    ShallowHistory { what: String, depth: u32 },
+
+    #[error("Authentication failed{}", .remote.as_ref().map(|remote| format!(" for {remote}")).unwrap_or_default())]
+    AuthenticationFailed { remote: Option<String> },
+
+    #[error("Local changes would be overwritten by checkout: {}", .files.join(", "))]
+    DirtyWorkingTree { files: Vec<String> },
+
+    #[error("Git command timed out after {timeout:?}")]
+    Timeout { timeout: Duration },
+
+    #[error("Failed to write {}: {source}", .path.display())]
+    OutputWrite { path: PathBuf, source: io::Error },
+
+    /// A path, or the content of a file (`<revision>:<path>`), that is not valid UTF-8
+    #[error("Not valid UTF-8: {path}")]
+    InvalidUtf8 { path: String },
+
     #[error("Failed to create temporary directory: {0}")]
     TempDirError(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Refusing to write outside the output directory: {0}")]
+    UnsafePath(String),
+
+    #[error("Failed to export {} file(s): {}", .0.len(), .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    ExportFailed(Vec<ExportFailure>),
+}
+
+impl Error {
+    /// Prefixes the message of an [`Error::InvalidConfig`] with the file it was read from
+    pub(crate) fn in_config_file(self, path: &Path) -> Self {
+        match self {
+            Self::InvalidConfig(message) => {
+                Self::InvalidConfig(format!("{}: {message}", path.display()))
+            }
+            e => e,
+        }
+    }
+
+    /// Classifies the stderr of a failed git command run in `repo_path`. This is the single
+    /// place that turns git's messages into error variants; anything unrecognised is kept as
+    /// [`Error::GitCommandError`].
+    #[must_use]
+    pub fn from_git_stderr(stderr: &str, repo_path: &Path) -> Self {
+        let lower = stderr.to_ascii_lowercase();
+
+        if lower.contains("not a git repository") {
+            return Self::NotARepository {
+                path: repo_path.to_path_buf(),
+            };
+        }
+
+        if lower.contains("would be overwritten by checkout") {
+            let files = stderr
+                .lines()
+                .filter(|line| line.starts_with('\t'))
+                .map(|line| line.trim().to_string())
+                .collect();
+            return Self::DirtyWorkingTree { files };
+        }
+
+        if AUTH_MESSAGES.iter().any(|message| lower.contains(message)) {
+            return Self::AuthenticationFailed {
+                remote: quoted_after(stderr, "for '").map(str::to_string),
+            };
+        }
+
+        if let Some(range) = stderr
+            .lines()
+            .find_map(|line| line.strip_suffix(": no merge base"))
+        {
+            let range = range.trim_start_matches("fatal: ");
+            if let Some((base, head)) = range.split_once("...") {
+                return Self::NoMergeBase {
+                    base: base.to_string(),
+                    head: head.to_string(),
+                };
+            }
+        }
+
+        let reference = quoted_after(stderr, "ambiguous argument '")
+            .or_else(|| quoted_after(stderr, "bad revision '"))
+            .or_else(|| quoted_after(stderr, "pathspec '"))
+            .or_else(|| quoted_after(stderr, "invalid reference: "))
+            .or_else(|| quoted_after(stderr, "couldn't find remote ref "))
+            .or_else(|| quoted_after(stderr, "Not a valid object name "));
+        if let Some(reference) = reference {
+            return Self::RefNotFound {
+                reference: reference.to_string(),
+            };
+        }
+
+        Self::GitCommandError(stderr.trim().to_string())
+    }
+
+    /// Returns true when `stderr` of a failed fetch says the ref or object does not exist on the
+    /// remote. Other failures, e.g. of the network, are not about the ref.
+    #[must_use]
+    pub(crate) fn is_missing_remote_object(stderr: &str) -> bool {
+        let lower = stderr.to_ascii_lowercase();
+        MISSING_REMOTE_OBJECT_MESSAGES
+            .iter()
+            .any(|message| lower.contains(message))
+    }
+
+    /// A suggestion on how to fix the error, for people running the CLI
+    #[must_use]
+    pub const fn hint(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::GitNotInstalled { .. } => {
+                "install git and make sure it is on `PATH`"
+            }
+            Self::NotARepository { .. } => {
+                "check the repository path, or pass a URL to clone a remote repository"
+            }
+            Self::RefNotFound { .. } => {
+                "check the spelling, and fetch the branch or commit if it only exists on the remote (e.g. `git fetch origin <branch>`)"
+            }
+            Self::NoMergeBase { .. } => {
+                "the histories are unrelated; compare the two trees directly with a `from..to` range"
+            }
+            Self::ShallowHistory { .. } => {
+                "fetch more history (e.g. `git fetch --unshallow`, or `fetch-depth: 0` in GitHub Actions) or raise the deepen limit"
+            }
+            Self::AuthenticationFailed { .. } => {
+                "provide credentials with an HTTPS token or an SSH key, and check they can read the repository"
+            }
+            Self::DirtyWorkingTree { .. } => {
+                "commit or stash the local changes, or clone without checking out files"
+            }
+            Self::Timeout { .. } => "raise the timeout, or make the clone smaller with a depth or filter",
+            Self::UnsafePath(_) => {
+                "the repository contains paths that would escape the output directory; they were not written"
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Returns the text after `prefix` up to the next quote (or end of line), e.g. the reference in
+/// `fatal: bad revision 'main'`
+fn quoted_after<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let start = text.find(prefix)? + prefix.len();
+    let rest = &text[start..];
+    let end = rest.find(['\'', '\n']).unwrap_or(rest.len());
+    let value = rest[..end].trim().trim_end_matches(['.', ':']);
+    (!value.is_empty()).then_some(value)
+}
+
+/// A file that could not be exported, and why
+#[derive(Debug, thiserror::Error)]
+#[error("{path}: {error}")]
+pub struct ExportFailure {
+    pub path: String,
+    pub error: Error,
 }
 
 /// Type alias for Result using the custom Error type
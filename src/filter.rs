@@ -0,0 +1,94 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::error::{Error, Result};
+
+/// Selects which changed paths are reported, see
+/// [`crate::processor::GitChangesProcessor::with_path_filter`].
+///
+/// Patterns are globs matched against repository paths: `*` and `?` do not cross `/` and `**`
+/// matches any number of directories. Like in `.gitignore`, a pattern without a `/` matches a
+/// name in any directory (`*.md`, `node_modules`) and one with a `/` is anchored at the root
+/// (`docs/generated`). A pattern naming a directory selects everything under it, unless its last
+/// component has a wildcard (`docs/*` selects the files directly in `docs`), and a trailing `/`
+/// only matches directories (`generated/`).
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    ignore: Vec<String>,
+    ignore_set: GlobSet,
+}
+
+impl PathFilter {
+    /// A filter that keeps every path
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leaves out the paths matching any of `patterns`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if a pattern is not a valid glob
+    pub fn ignore<I, S>(mut self, patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.ignore.extend(patterns.into_iter().map(Into::into));
+        self.ignore_set = glob_set(&self.ignore)?;
+        Ok(self)
+    }
+
+    /// Returns true when changes to `path` are reported
+    #[must_use]
+    pub fn matches(&self, path: &str) -> bool {
+        !self.ignore_set.is_match(path)
+    }
+
+    /// Returns true when the filter keeps every path
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.ignore.is_empty()
+    }
+}
+
+/// Compiles patterns with the syntax described on [`PathFilter`]
+pub fn glob_set<S: AsRef<str>>(patterns: &[S]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        for glob in pattern_globs(pattern) {
+            builder.add(
+                GlobBuilder::new(&glob)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| Error::InvalidConfig(format!("invalid glob `{pattern}`: {e}")))?,
+            );
+        }
+    }
+    builder
+        .build()
+        .map_err(|e| Error::InvalidConfig(e.to_string()))
+}
+
+/// Translates a `.gitignore` style pattern into the globs that match it, to be compiled with
+/// `literal_separator`. A pattern without a `/` matches a name in any directory and one with a
+/// `/` is anchored at the root. A matching directory selects everything under it, unless the
+/// last component has a wildcard, which only matches the entries directly in the directory.
+pub fn pattern_globs(pattern: &str) -> Vec<String> {
+    let directory = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    let glob = if trimmed.contains('/') {
+        trimmed.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{trimmed}")
+    };
+    let last = glob.rsplit('/').next().unwrap_or_default();
+    if directory {
+        vec![format!("{glob}/**")]
+    } else if last.contains(['*', '?', '[']) {
+        vec![glob]
+    } else {
+        vec![format!("{glob}/**"), glob]
+    }
+}
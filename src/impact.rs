@@ -0,0 +1,466 @@
+//! Selects the tests affected by a change set.
+//!
+//! Changed paths are mapped to tests in three ways, and the results are combined:
+//!
+//! - conventions: a changed test runs itself, and a source file maps to the test files named
+//!   after it (`src/foo.rs` → `tests/foo.rs` or `tests/foo_test.rs`, `x.ts` → `x.spec.ts` or
+//!   `x.test.ts`, `pkg/foo.py` → `pkg/test_foo.py` or `tests/test_foo.py`, `x.go` →
+//!   `x_test.go`). Only test files that exist are selected.
+//! - mappings from TOML, with one `[[test_mapping]]` table per mapping:
+//!
+//!   ```toml
+//!   [[test_mapping]]
+//!   paths = ["src/api/**", "openapi.yaml"]
+//!   tests = ["tests/api.rs"]
+//!   ```
+//!
+//! - coverage: tests that executed a changed file, from an lcov file with one `TN:` (test name)
+//!   per record, or a JSON object mapping each test to the files it covers.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::filter::glob_set;
+use crate::ChangeSet;
+
+/// Maps changed paths to the tests that should run
+#[derive(Debug, Clone)]
+pub struct TestImpact {
+    conventions: bool,
+    mappings: Vec<TestMapping>,
+    /// Covered source path → tests that executed it
+    coverage: BTreeMap<String, BTreeSet<String>>,
+}
+
+#[derive(Debug, Clone)]
+struct TestMapping {
+    paths: GlobSet,
+    tests: Vec<String>,
+}
+
+/// Test mappings as written in TOML
+#[derive(Deserialize)]
+struct ImpactConfig {
+    #[serde(default)]
+    test_mapping: Vec<MappingConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingConfig {
+    paths: Vec<String>,
+    tests: Vec<String>,
+}
+
+/// Tests selected by [`TestImpact::select`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestSelection {
+    /// Test files, or test names from mappings and coverage
+    pub tests: BTreeSet<String>,
+    /// Changed paths that no test maps to. Callers that want to stay safe run every test when
+    /// this is not empty.
+    pub unmapped: Vec<String>,
+}
+
+/// Test runner that [`TestSelection::to_invocations`] formats the selection for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestRunner {
+    /// `cargo test` arguments: one invocation with `--test <name>` per integration test
+    /// target, and one with the other tests as name filters after `--`. They cannot be
+    /// combined, as `--test` leaves out the tests of the library and binaries the filters may
+    /// name. Test files under `src/` are filtered by their module path, and test files of other
+    /// languages are left out.
+    Cargo,
+    /// `pytest` arguments: test files and node ids
+    Pytest,
+    /// `jest` arguments: test files
+    Jest,
+}
+
+impl Default for TestImpact {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestImpact {
+    /// Selects tests by convention only
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            conventions: true,
+            mappings: Vec::new(),
+            coverage: BTreeMap::new(),
+        }
+    }
+
+    /// Enables or disables the naming conventions (enabled by default)
+    #[must_use]
+    pub const fn with_conventions(mut self, enabled: bool) -> Self {
+        self.conventions = enabled;
+        self
+    }
+
+    /// Runs `tests` when a path matching one of the `paths` globs changes. Globs have the syntax
+    /// of [`crate::PathFilter`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if a glob is invalid
+    pub fn with_mapping<S: AsRef<str>>(mut self, paths: &[S], tests: &[S]) -> Result<Self> {
+        self.mappings.push(TestMapping {
+            paths: glob_set(paths)?,
+            tests: tests.iter().map(|test| test.as_ref().to_string()).collect(),
+        });
+        Ok(self)
+    }
+
+    /// Adds the `[[test_mapping]]` tables of a TOML configuration. Other tables are ignored, so
+    /// the mappings can live in the same file as a [`crate::policy::Policy`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the configuration or one of its globs is invalid
+    pub fn with_toml(mut self, toml: &str) -> Result<Self> {
+        let config: ImpactConfig =
+            toml::from_str(toml).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        for mapping in config.test_mapping {
+            self = self.with_mapping(&mapping.paths, &mapping.tests)?;
+        }
+        Ok(self)
+    }
+
+    /// Adds the tests recorded in lcov coverage data. Every record must be named with a `TN:`
+    /// line; unnamed records cannot be attributed to a test and are skipped.
+    #[must_use]
+    pub fn with_lcov(mut self, lcov: &str) -> Self {
+        let mut test = String::new();
+        for line in lcov.lines() {
+            if let Some(name) = line.strip_prefix("TN:") {
+                test = name.trim().to_string();
+            } else if let Some(source) = line.strip_prefix("SF:") {
+                if !test.is_empty() {
+                    self.add_coverage(&test, source.trim());
+                }
+            }
+        }
+        self
+    }
+
+    /// Adds the tests recorded in a JSON object mapping each test to the files it covers, e.g.
+    /// `{"tests/api.rs": ["src/api.rs", "src/db.rs"]}`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the JSON does not have this shape
+    pub fn with_coverage_json(mut self, json: &str) -> Result<Self> {
+        let coverage: BTreeMap<String, Vec<String>> = serde_json::from_str(json)
+            .map_err(|e| Error::InvalidConfig(format!("invalid coverage map: {e}")))?;
+        for (test, sources) in coverage {
+            for source in sources {
+                self.add_coverage(&test, &source);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Reads a mapping configuration (TOML) or coverage file (`.json`, otherwise lcov)
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the file cannot be read or is invalid
+    pub fn load(self, path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("cannot read {}: {e}", path.display())))?;
+        let with_path = |e: Error| e.in_config_file(path);
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => self.with_toml(&content).map_err(with_path),
+            Some("json") => self.with_coverage_json(&content).map_err(with_path),
+            _ => Ok(self.with_lcov(&content)),
+        }
+    }
+
+    fn add_coverage(&mut self, test: &str, source: &str) {
+        let source = source.trim_start_matches("./").replace('\\', "/");
+        self.coverage
+            .entry(source)
+            .or_default()
+            .insert(test.to_string());
+    }
+
+    /// Selects the tests for `changes`. `repo_files` holds the files of the repository after the
+    /// changes, which convention-based candidates are checked against.
+    #[must_use]
+    pub fn select(&self, changes: &ChangeSet, repo_files: &BTreeSet<String>) -> TestSelection {
+        let mut selection = TestSelection::default();
+        for path in changes.paths() {
+            let mut tests = BTreeSet::new();
+            if self.conventions {
+                tests.extend(
+                    convention_tests(path)
+                        .into_iter()
+                        .filter(|test| repo_files.contains(test)),
+                );
+            }
+            for mapping in &self.mappings {
+                if mapping.paths.is_match(path) {
+                    tests.extend(mapping.tests.iter().cloned());
+                }
+            }
+            // Coverage tools often record absolute paths, so match them by suffix
+            for (source, covering) in &self.coverage {
+                let matches = source == path
+                    || source
+                        .strip_suffix(path)
+                        .is_some_and(|prefix| prefix.ends_with('/'));
+                if matches {
+                    tests.extend(covering.iter().cloned());
+                }
+            }
+
+            // Helper modules shared by integration tests run the tests that may use them
+            let mut tests: BTreeSet<String> = tests
+                .into_iter()
+                .flat_map(|test| helper_targets(&test, repo_files).unwrap_or_else(|| vec![test]))
+                .collect();
+            if tests.is_empty() {
+                selection.unmapped.push(path.to_string());
+            }
+            selection.tests.append(&mut tests);
+        }
+        selection
+    }
+}
+
+impl TestSelection {
+    /// Formats the selected tests as command line arguments for `runner`, one list per run of
+    /// the runner. Nothing is returned when no test is selected.
+    #[must_use]
+    pub fn to_invocations(&self, runner: TestRunner) -> Vec<Vec<String>> {
+        match runner {
+            TestRunner::Cargo => {
+                let mut targets = BTreeSet::new();
+                let mut filters = BTreeSet::new();
+                for test in &self.tests {
+                    match cargo_test(test) {
+                        Some(CargoTest::Target(target)) => {
+                            targets.insert(target);
+                        }
+                        Some(CargoTest::Filter(filter)) => {
+                            filters.insert(filter);
+                        }
+                        None => {}
+                    }
+                }
+                let mut invocations = Vec::new();
+                if !targets.is_empty() {
+                    invocations.push(
+                        targets
+                            .into_iter()
+                            .flat_map(|target| ["--test".to_string(), target.to_string()])
+                            .collect(),
+                    );
+                }
+                if !filters.is_empty() {
+                    let mut args = vec!["--".to_string()];
+                    args.extend(filters);
+                    invocations.push(args);
+                }
+                invocations
+            }
+            TestRunner::Pytest | TestRunner::Jest => {
+                if self.tests.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![self.tests.iter().cloned().collect()]
+                }
+            }
+        }
+    }
+
+    /// Selected tests that [`TestSelection::to_invocations`] cannot pass to `runner`. Callers
+    /// should fail, or run every test, when this is not empty: leaving them out would report
+    /// success without running them.
+    #[must_use]
+    pub fn unrunnable(&self, runner: TestRunner) -> Vec<&str> {
+        match runner {
+            TestRunner::Cargo => self
+                .tests
+                .iter()
+                .filter(|test| is_rust_file(test) && cargo_test(test).is_none())
+                .map(String::as_str)
+                .collect(),
+            TestRunner::Pytest | TestRunner::Jest => Vec::new(),
+        }
+    }
+}
+
+/// How `cargo test` selects a test
+#[derive(Debug, PartialEq, Eq)]
+enum CargoTest<'a> {
+    /// An integration test target, passed as `--test <name>`
+    Target(&'a str),
+    /// A test name filter, passed after `--`
+    Filter(String),
+}
+
+/// Maps a selected test to `cargo test` arguments. Files directly under a `tests/` directory
+/// are integration test targets, as is every file under `tests/<name>/`: [`TestImpact::select`]
+/// only keeps those when `tests/<name>/main.rs` exists, and replaces shared helper modules with
+/// the targets that may use them. Files under `src/`
+/// become the path of their module, which matches the names of the tests inside it. Names
+/// without a `/` or `.` are filters already. Other Rust files, such as crate roots, return
+/// `None`, as do the files of other languages.
+fn cargo_test(test: &str) -> Option<CargoTest<'_>> {
+    let Some(file) = test.strip_suffix(".rs") else {
+        let is_name = !test.contains(['/', '.']);
+        return is_name.then(|| CargoTest::Filter(test.to_string()));
+    };
+    let components: Vec<&str> = file.split('/').collect();
+    let root = components
+        .iter()
+        .position(|component| matches!(*component, "src" | "tests"))?;
+    let rest = &components[root + 1..];
+    if components[root] == "tests" {
+        // `tests/api.rs`, or `tests/api/main.rs` and the modules next to it
+        return rest.first().map(|target| CargoTest::Target(target));
+    }
+
+    // Each file under `src/bin/` is the root of a binary, as is each `src/bin/<name>/main.rs`
+    let module = match rest {
+        ["bin", _] | ["bin", _, "main"] => return None,
+        ["bin", _, module @ ..] | module => module,
+    };
+    let module = match module {
+        [] | ["lib" | "main"] => return None,
+        [parents @ .., "mod"] => parents,
+        module => module,
+    };
+    (!module.is_empty()).then(|| CargoTest::Filter(module.join("::")))
+}
+
+/// Integration test files of the crate of `test` when it is a helper module under `tests/<dir>/`,
+/// such as `tests/common/mod.rs`: cargo only builds `tests/<dir>/main.rs` as a test target, and
+/// the other files are modules that any test target may include. `None` when `test` is not such
+/// a helper.
+fn helper_targets(test: &str, repo_files: &BTreeSet<String>) -> Option<Vec<String>> {
+    if !is_rust_file(test) {
+        return None;
+    }
+    let components: Vec<&str> = test.split('/').collect();
+    let root = components
+        .iter()
+        .position(|component| matches!(*component, "src" | "tests"))?;
+    let [dir, _, ..] = &components[root + 1..] else {
+        return None;
+    };
+    if components[root] != "tests" {
+        return None;
+    }
+    let tests_dir = format!("{}/", components[..=root].join("/"));
+    if repo_files.contains(&format!("{tests_dir}{dir}/main.rs")) {
+        return None;
+    }
+    Some(
+        repo_files
+            .iter()
+            .filter(|file| {
+                file.strip_prefix(&tests_dir).is_some_and(|target| {
+                    let is_file = !target.contains('/')
+                        && Path::new(target).extension().is_some_and(|ext| ext == "rs");
+                    is_file
+                        || target
+                            .split_once('/')
+                            .is_some_and(|(_, main)| main == "main.rs")
+                })
+            })
+            .cloned()
+            .collect(),
+    )
+}
+
+fn is_rust_file(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext == "rs")
+}
+
+/// Returns true when `path` is a test file by the usual naming conventions
+fn is_test_file(path: &str) -> bool {
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    let Some((stem, ext)) = file.rsplit_once('.') else {
+        return false;
+    };
+    let in_test_dir = dir
+        .split('/')
+        .any(|component| matches!(component, "tests" | "test" | "__tests__"));
+    match ext {
+        "rs" => in_test_dir || stem.ends_with("_test"),
+        "py" => stem.starts_with("test_") || stem.ends_with("_test"),
+        "go" => stem.ends_with("_test"),
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => {
+            in_test_dir || matches!(stem.rsplit_once('.'), Some((_, "spec" | "test")))
+        }
+        _ => false,
+    }
+}
+
+/// Test files that, by convention, test `path`
+fn convention_tests(path: &str) -> Vec<String> {
+    if is_test_file(path) {
+        return vec![path.to_string()];
+    }
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    let Some((stem, ext)) = file.rsplit_once('.') else {
+        return Vec::new();
+    };
+    let in_dir = |name: &str| {
+        if dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{dir}/{name}")
+        }
+    };
+
+    let mut tests = Vec::new();
+    match ext {
+        "rs" => {
+            // `crates/foo/src/bar/mod.rs` is tested by `crates/foo/tests/bar.rs`
+            let components: Vec<&str> = dir.split('/').collect();
+            let Some(src) = components.iter().rposition(|component| *component == "src") else {
+                return tests;
+            };
+            let root = match &components[..src] {
+                [] => String::new(),
+                parents => format!("{}/", parents.join("/")),
+            };
+            let name = match (stem, &components[src + 1..]) {
+                ("mod", [.., module]) => *module,
+                ("mod" | "lib" | "main", _) => return tests,
+                _ => stem,
+            };
+            tests.push(format!("{root}tests/{name}.rs"));
+            tests.push(format!("{root}tests/{name}_test.rs"));
+        }
+        "py" => {
+            tests.push(in_dir(&format!("test_{stem}.py")));
+            tests.push(in_dir(&format!("{stem}_test.py")));
+            tests.push(format!("tests/test_{stem}.py"));
+            if !dir.is_empty() {
+                tests.push(format!("tests/{dir}/test_{stem}.py"));
+            }
+        }
+        "go" => tests.push(in_dir(&format!("{stem}_test.go"))),
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => {
+            for kind in ["spec", "test"] {
+                tests.push(in_dir(&format!("{stem}.{kind}.{ext}")));
+                tests.push(in_dir(&format!("__tests__/{stem}.{kind}.{ext}")));
+            }
+            tests.push(in_dir(&format!("__tests__/{stem}.{ext}")));
+        }
+        _ => {}
+    }
+    tests
+}
@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+/// First line of every Git LFS pointer file
+const POINTER_VERSION_PREFIX: &str = "version https://git-lfs.github.com/spec/";
+
+/// Pointer files are tiny; anything larger is real content
+pub const MAX_POINTER_SIZE: u64 = 1024;
+
+/// Parses a Git LFS pointer, returning the object id and size
+#[must_use]
+pub fn parse_pointer(content: &[u8]) -> Option<(String, u64)> {
+    if content.len() as u64 > MAX_POINTER_SIZE {
+        return None;
+    }
+    let text = std::str::from_utf8(content).ok()?;
+    let mut lines = text.lines();
+    if !lines.next()?.starts_with(POINTER_VERSION_PREFIX) {
+        return None;
+    }
+
+    let mut oid = None;
+    let mut size = None;
+    for line in lines {
+        if let Some(value) = line.strip_prefix("oid ") {
+            oid = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("size ") {
+            size = value.trim().parse::<u64>().ok();
+        }
+    }
+
+    Some((oid?, size?))
+}
+
+/// Returns the location of an object in the local LFS store (`<git-dir>/lfs/objects/ab/cd/abcd...`)
+#[must_use]
+pub fn object_path(git_common_dir: &Path, oid: &str) -> Option<PathBuf> {
+    let hash = oid.strip_prefix("sha256:")?;
+    if hash.len() < 5 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(
+        git_common_dir
+            .join("lfs")
+            .join("objects")
+            .join(&hash[0..2])
+            .join(&hash[2..4])
+            .join(hash),
+    )
+}
+
+/// Returns true when the object at `object_path` has the `size` and the sha256 `oid` of its
+/// pointer, so a truncated or corrupt object is never exported as the real content
+///
+/// # Errors
+///
+/// Returns an error if the object cannot be read
+pub fn verify_object(object_path: &Path, oid: &str, size: u64) -> io::Result<bool> {
+    let Some(expected) = oid.strip_prefix("sha256:") else {
+        return Ok(false);
+    };
+    let mut file = File::open(object_path)?;
+    if file.metadata()?.len() != size {
+        return Ok(false);
+    }
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    let actual = format!("{:x}", hasher.finalize());
+    Ok(actual.eq_ignore_ascii_case(expected))
+}
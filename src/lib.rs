@@ -1,9 +1,30 @@
-pub use error::{Error, Result};
-pub use types::{FileChange, FileStatus};
+pub use builder::{Backend, ProcessorBuilder, RepoSource};
+pub use changeset::{ChangeSet, ChangeStats, SortOrder};
+pub use error::{Error, ExportFailure, Result};
+pub use filter::PathFilter;
+pub use spec::{DiffSpec, ExportOptions};
+pub use types::{
+    Commit, EntryKind, FileChange, FileCommit, FileStatus, LfsPointer, Signature, SubmoduleChange,
+    Trailer,
+};
 
+mod builder;
+pub mod cache;
+mod changeset;
+pub mod conventional;
+mod diff;
 mod error;
+mod filter;
 mod git;
+pub mod impact;
+mod lfs;
+mod log;
+mod output;
+pub mod owners;
+pub mod policy;
 pub mod processor;
+pub mod secrets;
+mod spec;
 mod types;
 
 /// Top-level factory function to create a `GitChangesProcessor`.
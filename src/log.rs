@@ -0,0 +1,87 @@
+//! Commits with their metadata and the paths they changed, parsed from the output of `git log`.
+//!
+//! Fields are separated by control characters and paths are NUL-terminated, so subjects,
+//! bodies and paths are kept verbatim whatever they contain.
+
+use crate::{Commit, Signature, Trailer};
+use tracing::debug;
+
+/// Separates commits in the output of [`LOG_ARGS`]
+const RECORD_SEPARATOR: char = '\x1e';
+
+/// Separates the fields of a commit in the output of [`LOG_ARGS`]
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// Arguments of a `git log` whose output [`parse_log`] reads. Each commit is a record of
+/// separated fields, followed by the paths it changed, each terminated by NUL so paths are kept
+/// verbatim. Topological order keeps the commits of merged branches together and does not
+/// depend on clocks.
+pub const LOG_ARGS: [&str; 7] = [
+    "log",
+    "--topo-order",
+    "-z",
+    "--name-only",
+    "--no-renames",
+    "--no-color",
+    "--format=%x1e%H%x1f%P%x1f%an%x1f%ae%x1f%aI%x1f%cn%x1f%ce%x1f%cI%x1f%s%x1f%b%x1f%(trailers:unfold,only)%x1f",
+];
+
+/// Parses the output of a `git log` run with [`LOG_ARGS`], keeping the paths `keep_path` accepts
+pub fn parse_log(output: &str, keep_path: impl Fn(&str) -> bool) -> Vec<Commit> {
+    let mut commits: Vec<Commit> = Vec::new();
+    for token in output.split('\0') {
+        let token = token.strip_prefix('\n').unwrap_or(token);
+        if let Some(record) = token.strip_prefix(RECORD_SEPARATOR) {
+            if let Some(commit) = parse_record(record) {
+                commits.push(commit);
+            } else {
+                debug!(record, "Unrecognized log record");
+            }
+        } else if !token.is_empty() && keep_path(token) {
+            if let Some(commit) = commits.last_mut() {
+                commit.paths.push(token.to_string());
+            }
+        }
+    }
+    commits
+}
+
+/// Parses the fields of one commit
+fn parse_record(record: &str) -> Option<Commit> {
+    let mut fields = record.split(FIELD_SEPARATOR);
+    let mut next = || fields.next().map(str::to_string);
+    let sha = next()?;
+    let parents = next()?.split_whitespace().map(str::to_string).collect();
+    let author = Signature {
+        name: next()?,
+        email: next()?,
+        date: next()?,
+    };
+    let committer = Signature {
+        name: next()?,
+        email: next()?,
+        date: next()?,
+    };
+    let subject = next()?;
+    let body = next()?.trim_end().to_string();
+    let trailers = next()?
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some(Trailer {
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+            })
+        })
+        .collect();
+    Some(Commit {
+        sha,
+        parents,
+        author,
+        committer,
+        subject,
+        body,
+        trailers,
+        paths: Vec::new(),
+    })
+}
@@ -0,0 +1,154 @@
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::{ChangeSet, FileChange};
+
+/// Manifest of an export, see [`crate::ExportOptions::with_manifest`]
+#[derive(Serialize)]
+struct Manifest<'a> {
+    changes: Vec<&'a FileChange>,
+    /// Git LFS pointers exported as pointers rather than the real object
+    unresolved_lfs: Vec<&'a str>,
+}
+
+/// Creates the output directory if needed and returns its canonical path, which every
+/// destination is checked against.
+pub fn prepare_root(output_dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(output_dir).map_err(write_error(output_dir))?;
+    Ok(output_dir.canonicalize()?)
+}
+
+/// Resolves the destination of a repository path inside `output_root` and gets it ready for
+/// writing.
+///
+/// The path must be relative and may only contain normal components. Parent directories are
+/// created one by one and must not be symlinks, the resolved parent must stay under
+/// `output_root`, and any existing file or symlink at the destination is removed (never
+/// followed) so that it can be created fresh.
+///
+/// # Errors
+///
+/// Returns [`Error::UnsafePath`] if the destination would escape `output_root`, and
+/// [`Error::OutputWrite`] if a file is in the way of a parent directory or a directory is at
+/// the destination
+pub fn prepare_destination(output_root: &Path, relative: &str) -> Result<PathBuf> {
+    let relative_path = Path::new(relative);
+    let components: Vec<&std::ffi::OsStr> = relative_path
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => Ok(name),
+            _ => Err(Error::UnsafePath(relative.to_string())),
+        })
+        .collect::<Result<_>>()?;
+    let Some((file_name, parents)) = components.split_last() else {
+        return Err(Error::UnsafePath(relative.to_string()));
+    };
+
+    let mut current = output_root.to_path_buf();
+    for parent in parents {
+        current.push(parent);
+        match current.symlink_metadata() {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(metadata) => return Err(not_a_directory(relative, &current, &metadata)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                match std::fs::create_dir(&current) {
+                    Ok(()) => {}
+                    // Another export worker created it first
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                        let metadata = current.symlink_metadata()?;
+                        if !metadata.is_dir() {
+                            return Err(not_a_directory(relative, &current, &metadata));
+                        }
+                    }
+                    Err(e) => return Err(write_error(&current)(e)),
+                }
+            }
+            Err(e) => return Err(Error::IoError(e)),
+        }
+    }
+
+    if !current.canonicalize()?.starts_with(output_root) {
+        debug!(path = %current.display(), "Destination escapes the output directory");
+        return Err(Error::UnsafePath(relative.to_string()));
+    }
+
+    let destination = current.join(file_name);
+    if let Ok(metadata) = destination.symlink_metadata() {
+        if metadata.is_dir() {
+            debug!(path = %destination.display(), "Destination is an existing directory");
+            return Err(write_error(&destination)(std::io::Error::new(
+                std::io::ErrorKind::IsADirectory,
+                "a directory is in the way",
+            )));
+        }
+        std::fs::remove_file(&destination).map_err(write_error(&destination))?;
+    }
+
+    Ok(destination)
+}
+
+/// Error for a parent directory of `relative` that exists at `path` as something else. A symlink
+/// could lead outside the output directory and is unsafe, anything else is just in the way.
+fn not_a_directory(relative: &str, path: &Path, metadata: &std::fs::Metadata) -> Error {
+    if metadata.is_symlink() {
+        debug!(path = %path.display(), "Refusing to write through a symlink");
+        return Error::UnsafePath(relative.to_string());
+    }
+    write_error(path)(std::io::Error::new(
+        std::io::ErrorKind::NotADirectory,
+        "a file is in the way of a directory",
+    ))
+}
+
+/// Writes `content` to a destination returned by [`prepare_destination`]. The file is created
+/// with `create_new`, so a symlink planted at the destination in the meantime is never followed.
+pub fn write_file(destination: &Path, content: &[u8]) -> Result<()> {
+    create_file(destination)?
+        .write_all(content)
+        .map_err(write_error(destination))?;
+    Ok(())
+}
+
+/// Copies the content of `source` to a destination returned by [`prepare_destination`], like
+/// [`write_file`] but without reading the whole file into memory. Permissions are not copied.
+pub fn copy_file(destination: &Path, source: &Path) -> Result<()> {
+    let mut reader = File::open(source)?;
+    let mut file = create_file(destination)?;
+    std::io::copy(&mut reader, &mut file).map_err(write_error(destination))?;
+    Ok(())
+}
+
+fn create_file(destination: &Path) -> Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(destination)
+        .map_err(write_error(destination))
+}
+
+/// Maps an IO error writing `path` to [`Error::OutputWrite`]
+pub fn write_error(path: &Path) -> impl FnOnce(std::io::Error) -> Error + '_ {
+    move |source| Error::OutputWrite {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Writes the manifest of an export of `changes` to `path` as JSON
+pub fn write_manifest(path: &Path, changes: &ChangeSet) -> Result<()> {
+    let manifest = Manifest {
+        changes: changes.iter().collect(),
+        unresolved_lfs: changes
+            .unresolved_lfs()
+            .map(|change| change.path.as_str())
+            .collect(),
+    };
+    let json = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::from)?;
+    std::fs::write(path, json).map_err(write_error(path))?;
+    debug!(path = %path.display(), num_unresolved_lfs = manifest.unresolved_lfs.len(), "Wrote export manifest");
+    Ok(())
+}
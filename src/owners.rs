@@ -0,0 +1,81 @@
+//! Code owners of changed files, read from a GitHub style `CODEOWNERS` file.
+//!
+//! Every line holds a pattern followed by owners (`@user`, `@org/team` or an email address).
+//! The last matching line wins, and a line without owners leaves the matching paths unowned.
+//!
+//! Patterns follow `.gitignore` with GitHub's exception: `*` never matches into subdirectories,
+//! so `docs/*` owns `docs/index.md` but not `docs/build/index.md`.
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use tracing::debug;
+
+use crate::filter::pattern_globs;
+
+/// Where `CODEOWNERS` is looked up, in the order GitHub uses
+pub const CODEOWNERS_PATHS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Rules of a `CODEOWNERS` file
+#[derive(Debug, Clone, Default)]
+pub struct CodeOwners {
+    rules: Vec<OwnerRule>,
+}
+
+#[derive(Debug, Clone)]
+struct OwnerRule {
+    globs: GlobSet,
+    owners: Vec<String>,
+}
+
+impl CodeOwners {
+    /// Parses the content of a `CODEOWNERS` file. Lines with an invalid pattern are skipped,
+    /// like GitHub does.
+    #[must_use]
+    pub fn parse(content: &str) -> Self {
+        let rules = content
+            .lines()
+            .filter_map(|line| {
+                // `#` starts a comment, unless it is escaped
+                let line = line
+                    .char_indices()
+                    .find(|&(i, c)| c == '#' && !line[..i].ends_with('\\'))
+                    .map_or(line, |(i, _)| &line[..i]);
+                let mut fields = line.split_whitespace();
+                let pattern = fields.next()?.replace("\\#", "#");
+                let owners = fields.map(str::to_string).collect();
+                match owner_globs(&pattern) {
+                    Ok(globs) => Some(OwnerRule { globs, owners }),
+                    Err(e) => {
+                        debug!(pattern = %pattern, error = %e, "Skipping invalid CODEOWNERS line");
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Owners of `path`, empty when no rule assigns any
+    #[must_use]
+    pub fn owners_of(&self, path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.globs.is_match(path))
+            .map_or(&[], |rule| &rule.owners)
+    }
+
+    /// Returns true when there are no rules
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// Compiles a `CODEOWNERS` pattern, with the syntax of [`crate::PathFilter`]
+fn owner_globs(pattern: &str) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in pattern_globs(pattern) {
+        builder.add(GlobBuilder::new(&glob).literal_separator(true).build()?);
+    }
+    builder.build()
+}
@@ -0,0 +1,295 @@
+//! Rules that a change set must satisfy, e.g. to fail a CI build.
+//!
+//! A policy is read from TOML, with one `[[rule]]` table per rule:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "changelog"
+//! type = "require"
+//! when = ["migrations/**"]
+//! require = ["CHANGELOG.md"]
+//! message = "Database migrations must be documented in the changelog"
+//!
+//! [[rule]]
+//! name = "lockfile"
+//! type = "require"
+//! when = ["*.lock"]
+//! statuses = ["modified"]
+//! require = ["Cargo.toml"]
+//!
+//! [[rule]]
+//! name = "pr-size"
+//! type = "max-files"
+//! max = 50
+//! severity = "warning"
+//!
+//! [[rule]]
+//! name = "generated"
+//! type = "forbid"
+//! paths = ["generated/"]
+//! ```
+//!
+//! Paths are globs with the syntax of [`crate::PathFilter`].
+
+use std::path::Path;
+
+use globset::GlobSet;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::filter::glob_set;
+use crate::{ChangeSet, FileChange, FileStatus};
+
+/// How a violation affects the outcome of a check
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Reported without failing the check
+    Warning,
+    /// Fails the check
+    #[default]
+    Error,
+}
+
+/// A rule that was broken by the change set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    /// Name of the rule
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Changed paths that caused the violation, if it is about specific files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+}
+
+/// A set of rules evaluated against a [`ChangeSet`]
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    severity: Severity,
+    message: Option<String>,
+    statuses: Vec<FileStatus>,
+    check: Check,
+}
+
+#[derive(Debug, Clone)]
+enum Check {
+    Require {
+        when: GlobSet,
+        require: GlobSet,
+        require_patterns: Vec<String>,
+    },
+    Forbid {
+        paths: GlobSet,
+    },
+    MaxFiles {
+        paths: Option<GlobSet>,
+        max: usize,
+    },
+}
+
+/// Policy file as written in TOML. The file also holds the settings of other features, whose
+/// tables are skipped here; any other key is rejected so that a typo cannot disable the policy.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyConfig {
+    #[serde(default)]
+    rule: Vec<RuleConfig>,
+    /// Read by [`crate::impact::TestImpact`]
+    #[serde(default, rename = "test_mapping")]
+    _test_mapping: IgnoredAny,
+    /// Read by [`crate::secrets::SecretScanner`]
+    #[serde(default, rename = "secrets")]
+    _secrets: IgnoredAny,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    name: String,
+    #[serde(rename = "type")]
+    kind: RuleKind,
+    #[serde(default)]
+    severity: Severity,
+    message: Option<String>,
+    /// Only changes with one of these statuses are considered (all by default)
+    #[serde(default)]
+    statuses: Vec<FileStatus>,
+    when: Option<Vec<String>>,
+    require: Option<Vec<String>>,
+    paths: Option<Vec<String>>,
+    max: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum RuleKind {
+    /// When a path matching `when` changed, a path matching `require` must change too
+    Require,
+    /// Paths matching `paths` must not change
+    Forbid,
+    /// At most `max` paths (matching `paths`, if set) may change
+    MaxFiles,
+}
+
+impl Policy {
+    /// Parses a policy from TOML
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the policy or one of its globs is invalid
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let config: PolicyConfig =
+            toml::from_str(toml).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        let rules = config
+            .rule
+            .into_iter()
+            .map(Rule::compile)
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Reads a policy from a TOML file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or [`Error::InvalidConfig`] if it is invalid
+    pub fn load(path: &Path) -> Result<Self> {
+        let toml = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("cannot read {}: {e}", path.display())))?;
+        Self::from_toml(&toml).map_err(|e| e.in_config_file(path))
+    }
+
+    /// Number of rules
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Returns true when the policy has no rules
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Evaluates every rule against `changes`, returning the violations in rule order
+    #[must_use]
+    pub fn evaluate(&self, changes: &ChangeSet) -> Vec<Violation> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.evaluate(changes))
+            .collect()
+    }
+}
+
+impl Rule {
+    fn compile(config: RuleConfig) -> Result<Self> {
+        let missing =
+            |field: &str| Error::InvalidConfig(format!("rule `{}` needs `{field}`", config.name));
+        let check = match config.kind {
+            RuleKind::Require => {
+                let when = config.when.as_ref().ok_or_else(|| missing("when"))?;
+                let require = config.require.clone().ok_or_else(|| missing("require"))?;
+                Check::Require {
+                    when: glob_set(when)?,
+                    require: glob_set(&require)?,
+                    require_patterns: require,
+                }
+            }
+            RuleKind::Forbid => Check::Forbid {
+                paths: glob_set(config.paths.as_ref().ok_or_else(|| missing("paths"))?)?,
+            },
+            RuleKind::MaxFiles => Check::MaxFiles {
+                paths: config.paths.as_deref().map(glob_set).transpose()?,
+                max: config.max.ok_or_else(|| missing("max"))?,
+            },
+        };
+        Ok(Self {
+            name: config.name,
+            severity: config.severity,
+            message: config.message,
+            statuses: config.statuses,
+            check,
+        })
+    }
+
+    fn evaluate(&self, changes: &ChangeSet) -> Option<Violation> {
+        let considered = |change: &&FileChange| {
+            self.statuses.is_empty() || self.statuses.contains(&change.status)
+        };
+        let (paths, default_message) = match &self.check {
+            Check::Require {
+                when,
+                require,
+                require_patterns,
+            } => {
+                let triggers: Vec<String> = changes
+                    .iter()
+                    .filter(considered)
+                    .filter(|change| when.is_match(&change.path))
+                    .map(|change| change.path.clone())
+                    .collect();
+                if triggers.is_empty() || changes.paths().any(|path| require.is_match(path)) {
+                    return None;
+                }
+                let message = format!(
+                    "{} require a change to {}",
+                    describe(&triggers),
+                    require_patterns.join(" or ")
+                );
+                (triggers, message)
+            }
+            Check::Forbid { paths } => {
+                let forbidden: Vec<String> = changes
+                    .iter()
+                    .filter(considered)
+                    .filter(|change| paths.is_match(&change.path))
+                    .map(|change| change.path.clone())
+                    .collect();
+                if forbidden.is_empty() {
+                    return None;
+                }
+                let message = format!("{} must not be changed", describe(&forbidden));
+                (forbidden, message)
+            }
+            Check::MaxFiles { paths, max } => {
+                let count = changes
+                    .iter()
+                    .filter(considered)
+                    .filter(|change| {
+                        paths
+                            .as_ref()
+                            .is_none_or(|paths| paths.is_match(&change.path))
+                    })
+                    .count();
+                if count <= *max {
+                    return None;
+                }
+                let message = format!("{count} files changed, at most {max} are allowed");
+                (Vec::new(), message)
+            }
+        };
+
+        Some(Violation {
+            rule: self.name.clone(),
+            severity: self.severity,
+            message: self.message.clone().unwrap_or(default_message),
+            paths,
+        })
+    }
+}
+
+/// Names the paths in a message: the path itself, or a count when there are several
+fn describe(paths: &[String]) -> String {
+    match paths {
+        [path] => format!("Changes to {path}"),
+        _ => format!("Changes to {} files", paths.len()),
+    }
+}
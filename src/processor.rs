@@ -1,51 +1,351 @@
-use crate::error::Result;
-use crate::git::{Git, GitCli};
+use crate::builder::{ProcessorBuilder, RepoSource};
+use crate::conventional::{Package, ReleasePlan};
+use crate::diff;
+use crate::error::{Error, ExportFailure, Result};
+use crate::filter::PathFilter;
+use crate::git::{BlobReader, Git, GitCli};
+use crate::impact::{TestImpact, TestSelection};
+use crate::lfs;
+use crate::log;
+use crate::output;
+use crate::owners::{CodeOwners, CODEOWNERS_PATHS};
+use crate::secrets::{Finding, SecretScanner};
+use crate::spec::{DiffSpec, ExportOptions};
+use crate::ChangeSet;
+use crate::Commit;
+use crate::EntryKind;
 use crate::FileChange;
+use crate::FileCommit;
 use crate::FileStatus;
-use std::collections::HashMap;
-use std::path::Path;
+use crate::LfsPointer;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
 use tracing::{debug, instrument};
 
 /// Processes Git repository changes and manages output
+#[allow(clippy::struct_excessive_bools)] // Independent options, see the `with_*` methods
 pub struct GitChangesProcessor {
     git: GitCli,
+    resolve_lfs: bool,
+    recurse_submodules: bool,
+    jobs: usize,
+    deepen_limit: u32,
+    checkout_branches: bool,
+    path_filter: PathFilter,
+    code_owners: bool,
+    commit_attribution: Option<CommitAttribution>,
 }
 
+/// How [`FileChange::commits`] is computed, see
+/// [`GitChangesProcessor::with_commit_attribution`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitAttribution {
+    /// One `git log` over the range, listing the paths every commit changed. Changes that only
+    /// happened while resolving a merge are not attributed to the merge.
+    Cheap,
+    /// One `git log` per changed path, with git's history simplification, which also
+    /// attributes merges that changed the file. Runs a git process per path.
+    Precise,
+}
+
+/// Commits fetched by the first `git fetch --deepen` in a shallow clone, doubled on every retry
+const INITIAL_DEEPEN: u32 = 16;
+
+/// Number of paths passed to one `git diff` when only some of the changes need a patch
+const PATHSPEC_BATCH: usize = 500;
+
+/// Default for [`GitChangesProcessor::with_deepen_limit`]
+pub const DEFAULT_DEEPEN_LIMIT: u32 = 1000;
+
 impl GitChangesProcessor {
+    /// Starts configuring a processor, e.g.
+    /// `GitChangesProcessor::builder().remote(url).clone_depth(50).build()`
+    #[must_use]
+    pub fn builder() -> ProcessorBuilder {
+        ProcessorBuilder::default()
+    }
+
     /// Create a processor from a local git repository
     #[instrument(skip(path), fields(path = %path.display()))]
     pub fn new_from_local(path: &Path) -> Result<Self> {
         debug!("Initializing GitChangesProcessor from local repository path");
-
-        Ok(Self {
-            git: GitCli::new(path.to_path_buf()),
-        })
+        Self::builder().local(path).build()
     }
 
     /// Creates a new `GitChangesProcessor` from a repository source
-    /// The source can be either a local path or a remote URL (HTTPS/SSH)
+    /// The source can be either a local path or a remote URL (see [`RepoSource::parse`])
     ///
     /// # Errors
     ///
     /// Returns an error if the repository cannot be accessed or initialized
-    #[instrument(skip(), fields(repo = %repo))]
+    #[instrument(skip(repo), fields(repo = %RepoSource::parse(repo)))]
     pub fn new(repo: &str) -> Result<Self> {
         debug!("Initializing GitChangesProcessor from repository source");
+        Self::builder().repo(repo).build()
+    }
 
-        // Check if source is a URL (simple check for now)
-        let is_url = repo.starts_with("https://") || repo.starts_with("git@");
+    pub(crate) fn from_git(git: GitCli) -> Self {
+        Self {
+            git,
+            resolve_lfs: true,
+            recurse_submodules: false,
+            jobs: 1,
+            deepen_limit: DEFAULT_DEEPEN_LIMIT,
+            checkout_branches: true,
+            path_filter: PathFilter::new(),
+            code_owners: false,
+            commit_attribution: None,
+        }
+    }
 
-        if is_url {
-            debug!("Detected remote repository URL, proceeding with clone operation");
-            let git = GitCli::new_with_temp_workspace()?;
-            git.clone_repo(repo)?;
-            debug!("Successfully cloned remote repository");
+    /// Enables or disables resolving Git LFS pointers from the local LFS object store during export.
+    /// When disabled (or when the object is missing) the pointer text is exported and the change is
+    /// flagged with an unresolved [`LfsPointer`].
+    #[must_use]
+    pub const fn with_lfs_resolution(mut self, enabled: bool) -> Self {
+        self.resolve_lfs = enabled;
+        self
+    }
 
-            Ok(Self { git })
-        } else {
-            debug!("Detected local repository path, initializing from local filesystem");
-            Self::new_from_local(Path::new(repo))
+    /// Enables or disables reporting the file changes inside modified submodules.
+    /// Only submodules that are checked out locally and contain both commits are inspected.
+    #[must_use]
+    pub const fn with_submodule_recursion(mut self, enabled: bool) -> Self {
+        self.recurse_submodules = enabled;
+        self
+    }
+
+    /// Sets the number of worker threads used to export files (defaults to 1)
+    #[must_use]
+    pub const fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Enables or disables checking out the branch of [`DiffSpec::Branch`] and
+    /// [`DiffSpec::BranchVsDefault`] comparisons (enabled by default). When disabled the working
+    /// tree is left alone, and a branch without a local ref is read from `origin/<branch>`.
+    #[must_use]
+    pub const fn with_branch_checkout(mut self, enabled: bool) -> Self {
+        self.checkout_branches = enabled;
+        self
+    }
+
+    /// Sets how many commits of history may be fetched from origin when a shallow clone lacks
+    /// the merge base or parent commit a comparison needs (defaults to [`DEFAULT_DEEPEN_LIMIT`]).
+    /// `0` disables deepening.
+    #[must_use]
+    pub const fn with_deepen_limit(mut self, commits: u32) -> Self {
+        self.deepen_limit = commits;
+        self
+    }
+
+    /// Only reports (and exports) the changed paths kept by `filter`. Nested submodule changes
+    /// are not filtered.
+    #[must_use]
+    pub fn with_path_filter(mut self, filter: PathFilter) -> Self {
+        self.path_filter = filter;
+        self
+    }
+
+    /// Enables or disables setting [`FileChange::owners`] from the `CODEOWNERS` file of the
+    /// base revision of each comparison (the target branch, the parent commit, ...), which is the
+    /// one GitHub uses to request reviews
+    #[must_use]
+    pub const fn with_code_owners(mut self, enabled: bool) -> Self {
+        self.code_owners = enabled;
+        self
+    }
+
+    /// Enables setting [`FileChange::commits`] to the commits of the comparison that changed
+    /// each path, computed as described by `mode` (`None` disables it, the default). The
+    /// comparisons of the working tree and the index have no commits.
+    #[must_use]
+    pub const fn with_commit_attribution(mut self, mode: Option<CommitAttribution>) -> Self {
+        self.commit_attribution = mode;
+        self
+    }
+
+    /// Reads the `CODEOWNERS` file of `revision`, from the first of [`CODEOWNERS_PATHS`] that
+    /// exists. Returns `None` when there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if git cannot be run
+    #[instrument(skip(self))]
+    pub fn code_owners(&self, revision: &str) -> Result<Option<CodeOwners>> {
+        for path in CODEOWNERS_PATHS {
+            if let Some(content) = self.git.get_file_content(revision, path)? {
+                debug!(path, "Found CODEOWNERS");
+                return Ok(Some(CodeOwners::parse(&content)));
+            }
+        }
+        debug!("No CODEOWNERS file found");
+        Ok(None)
+    }
+
+    /// Lists the changes selected by `spec`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the revisions cannot be resolved or the changes cannot be retrieved
+    #[instrument(skip(self))]
+    pub fn list(&self, spec: &DiffSpec) -> Result<ChangeSet> {
+        let revs = self.resolve_spec(spec)?;
+        let changes = self
+            .filtered_changes(&revs)?
+            .collect::<Result<ChangeSet>>()?;
+        debug!(num_changes = changes.len(), "Listed changes");
+        Ok(changes)
+    }
+
+    /// Streams the changes selected by `spec` straight from `git diff`, without collecting them.
+    /// Memory use stays constant no matter how many paths changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the revisions cannot be resolved or the diff cannot be started.
+    /// Errors reading the diff are yielded by the iterator.
+    #[instrument(skip(self))]
+    pub fn stream(&self, spec: &DiffSpec) -> Result<impl Iterator<Item = Result<FileChange>> + '_> {
+        let revs = self.resolve_spec(spec)?;
+        self.filtered_changes(&revs)
+    }
+
+    /// Exports the changes selected by `spec` as described by `options`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the changes cannot be retrieved, or [`Error::ExportFailed`] listing
+    /// every file that could not be exported
+    #[instrument(skip(self))]
+    pub fn export(&self, spec: &DiffSpec, options: &ExportOptions) -> Result<ChangeSet> {
+        let revs = self.resolve_spec(spec)?;
+        let mut changes = self
+            .filtered_changes(&revs)?
+            .collect::<Result<ChangeSet>>()?;
+        debug!(num_files = changes.len(), "Retrieved changes to export");
+
+        self.export_changes(&mut changes, &revs, options)?;
+        if let Some(manifest) = &options.manifest {
+            output::write_manifest(manifest, &changes)?;
+        }
+        debug!(
+            num_files = changes.len(),
+            "Completed export of all file changes"
+        );
+        Ok(changes)
+    }
+
+    /// Selects the tests affected by the changes selected by `spec`. Convention-based test
+    /// files are looked up in the revision being compared (or the index for
+    /// [`DiffSpec::Worktree`] and [`DiffSpec::Index`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the changes or the files of the repository cannot be retrieved
+    #[instrument(skip(self, impact))]
+    pub fn select_tests(&self, spec: &DiffSpec, impact: &TestImpact) -> Result<TestSelection> {
+        let revs = self.resolve_spec(spec)?;
+        let changes = self
+            .filtered_changes(&revs)?
+            .collect::<Result<ChangeSet>>()?;
+        let files = self.repo_files(head_revision(&revs))?;
+        let selection = impact.select(&changes, &files);
+        debug!(
+            num_tests = selection.tests.len(),
+            num_unmapped = selection.unmapped.len(),
+            "Selected tests"
+        );
+        Ok(selection)
+    }
+
+    /// Lists the commits that make up the comparison selected by `spec`, children before their
+    /// parents: the commits of the branch since the merge base, the commits of a range, or the
+    /// commit itself (with the commits it merges, for a merge commit). The comparisons of the
+    /// working tree and the index have no commits. [`Commit::paths`] only holds the paths kept
+    /// by the path filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the revisions cannot be resolved or the history cannot be read
+    #[instrument(skip(self))]
+    pub fn list_commits(&self, spec: &DiffSpec) -> Result<Vec<Commit>> {
+        let revs = self.resolve_spec(spec)?;
+        self.commits(&revs)
+    }
+
+    /// Parses the commits of the comparison selected by `spec` as Conventional Commits and
+    /// suggests the version bump overall and for each package they changed. Packages are read
+    /// from the `Cargo.toml` and `package.json` files of the revision being compared.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the commits or the files of the repository cannot be read
+    #[instrument(skip(self))]
+    pub fn release_plan(&self, spec: &DiffSpec) -> Result<ReleasePlan> {
+        let revs = self.resolve_spec(spec)?;
+        let commits = self.commits(&revs)?;
+        let packages = self.workspace_packages(head_revision(&revs))?;
+        let plan = ReleasePlan::new(&commits, &packages);
+        debug!(bump = %plan.bump, num_packages = plan.packages.len(), "Planned release");
+        Ok(plan)
+    }
+
+    /// Scans the lines added by the changes selected by `spec` for secrets. Lines that were
+    /// already there, and deleted files, are not scanned. Findings are sorted by path and line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the changes or their diff cannot be retrieved
+    #[instrument(skip(self, scanner))]
+    pub fn scan_secrets(&self, spec: &DiffSpec, scanner: &SecretScanner) -> Result<Vec<Finding>> {
+        let revs = self.resolve_spec(spec)?;
+        let mut paths = Vec::new();
+        for change in self.filtered_changes(&revs)? {
+            let change = change?;
+            let has_text = !matches!(change.status, FileStatus::Deleted)
+                && change.entry_kind != EntryKind::Gitlink;
+            if has_text && !scanner.is_path_allowed(&change.path) {
+                paths.push(change.path);
+            }
         }
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Only the selected paths are diffed, a batch at a time, so the patches held in memory
+        // stay bounded and the command line stays within the system's limits
+        let mut findings = Vec::new();
+        for batch in paths.chunks(PATHSPEC_BATCH) {
+            let pathspecs: Vec<String> = batch
+                .iter()
+                .map(|path| format!(":(literal){path}"))
+                .collect();
+            let mut args = diff::PATCH_DIFF_ARGS.to_vec();
+            args.extend(["--unified=0", "--no-textconv"]);
+            args.extend(revs.iter().map(String::as_str));
+            args.push("--");
+            args.extend(pathspecs.iter().map(String::as_str));
+            let patches = self.git.diff_per_file(&args)?;
+
+            for path in batch {
+                let Some(patch) = patches.get(path) else {
+                    continue;
+                };
+                for (line_number, line) in diff::added_lines(patch) {
+                    findings.extend(scanner.scan_line(path, line_number, &line));
+                }
+            }
+        }
+        debug!(
+            num_files = paths.len(),
+            num_findings = findings.len(),
+            "Scanned added lines for secrets"
+        );
+        Ok(findings)
     }
 
     /// Exports changes between a branch and the default branch to the specified output directory
@@ -53,16 +353,15 @@ impl GitChangesProcessor {
     /// # Errors
     ///
     /// Returns an error if the changes cannot be exported or the output directory is not accessible
-    #[instrument(skip(self, output_dir), fields(branch = %branch, output_dir = %output_dir.display()))]
     pub fn export_changes_from_default_branch(
         &self,
         branch: &str,
         output_dir: &Path,
-    ) -> Result<HashMap<String, FileChange>> {
-        debug!("Exporting changes from branch to default branch");
-        let target_branch = self.git.discover_default_branch()?;
-        debug!(target_branch = %target_branch, "Discovered default branch, proceeding with export");
-        self.export_branch_changes(branch, &target_branch, output_dir)
+    ) -> Result<ChangeSet> {
+        self.export(
+            &DiffSpec::branch_vs_default(branch),
+            &ExportOptions::new(output_dir),
+        )
     }
 
     /// Exports changes between two branches to the specified output directory
@@ -70,61 +369,28 @@ impl GitChangesProcessor {
     /// # Errors
     ///
     /// Returns an error if the changes cannot be exported or the output directory is not accessible
-    #[instrument(skip(self, output_dir), fields(branch = %branch, target_branch = %target_branch, output_dir = %output_dir.display()))]
     pub fn export_branch_changes(
         &self,
         branch: &str,
         target_branch: &str,
         output_dir: &Path,
-    ) -> Result<HashMap<String, FileChange>> {
-        debug!("Starting export of changes between branches");
-        self.git.checkout_branch(branch)?;
-        let change_files = self.get_changes_for_branch(branch, target_branch)?;
-        debug!(
-            num_files = change_files.len(),
-            "Retrieved changes for branch comparison"
-        );
+    ) -> Result<ChangeSet> {
+        self.export(
+            &DiffSpec::branch(branch, target_branch),
+            &ExportOptions::new(output_dir),
+        )
+    }
 
-        for (path_str, file_status) in &change_files {
-            let output_file_path = output_dir.join(path_str);
-            debug!(
-                file_path = %path_str,
-                status = ?file_status.status,
-                "Processing file change"
-            );
-            match file_status.status {
-                FileStatus::Added => {
-                    self.git.run_git_command_to_file(
-                        &["show", &format!("{branch}:{path_str}")],
-                        &output_file_path,
-                    )?;
-                }
-                FileStatus::Modified => {
-                    self.git.run_git_command_to_file(
-                        &["show", &format!("{branch}:{path_str}")],
-                        &output_file_path,
-                    )?;
-                    let diff_file_path = output_dir.join(format!("{path_str}.diff"));
-                    self.git.run_git_command_to_file(
-                        &[
-                            "diff",
-                            &format!("{target_branch}...{branch}"),
-                            "--",
-                            path_str,
-                        ],
-                        &diff_file_path,
-                    )?;
-                }
-                FileStatus::Deleted => {
-                    debug!("Skipping export of deleted file");
-                }
-            }
-        }
-        debug!(
-            num_files = change_files.len(),
-            "Completed export of all file changes"
-        );
-        Ok(change_files)
+    /// Exports changes from a specific commit to the specified output directory
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the changes cannot be exported or the output directory is not accessible
+    pub fn export_commit_changes(&self, commit_hash: &str, output_dir: &Path) -> Result<ChangeSet> {
+        self.export(
+            &DiffSpec::commit(commit_hash),
+            &ExportOptions::new(output_dir),
+        )
     }
 
     /// Lists changes between two branches
@@ -132,15 +398,8 @@ impl GitChangesProcessor {
     /// # Errors
     ///
     /// Returns an error if the branch changes cannot be retrieved
-    #[instrument(skip(self), fields(branch = %branch, target_branch = %target_branch))]
-    pub fn list_branch_changes(
-        &self,
-        branch: &str,
-        target_branch: &str,
-    ) -> Result<HashMap<String, FileChange>> {
-        debug!("Listing changes between branches");
-        self.git.checkout_branch(branch)?;
-        self.get_changes_for_branch(branch, target_branch)
+    pub fn list_branch_changes(&self, branch: &str, target_branch: &str) -> Result<ChangeSet> {
+        self.list(&DiffSpec::branch(branch, target_branch))
     }
 
     /// Lists changes from a branch to the default branch
@@ -148,142 +407,414 @@ impl GitChangesProcessor {
     /// # Errors
     ///
     /// Returns an error if the branch changes cannot be retrieved
-    #[instrument(skip(self), fields(branch = %branch))]
-    pub fn list_changes_from_default_branch(
-        &self,
-        branch: &str,
-    ) -> Result<HashMap<String, FileChange>> {
-        debug!("Listing changes from branch to default branch");
-        self.git.checkout_branch(branch)?;
-        let target_branch = self.git.discover_default_branch()?;
-        debug!(target_branch = %target_branch, "Discovered default branch, proceeding with change list");
-        self.get_changes_for_branch(branch, &target_branch)
+    pub fn list_changes_from_default_branch(&self, branch: &str) -> Result<ChangeSet> {
+        self.list(&DiffSpec::branch_vs_default(branch))
     }
 
-    #[instrument(skip(self), fields(branch_name = %branch_name, target_branch = %target_branch))]
-    fn get_changes_for_branch(
+    /// Lists changes in a specific commit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the commit changes cannot be retrieved
+    pub fn list_commit_changes(&self, commit_hash: &str) -> Result<ChangeSet> {
+        self.list(&DiffSpec::commit(commit_hash))
+    }
+
+    /// Streams changes between two branches straight from `git diff`, without collecting them
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the branch cannot be checked out or the diff cannot be started.
+    /// Errors reading the diff are yielded by the iterator.
+    pub fn iter_changes(
         &self,
-        branch_name: &str,
+        branch: &str,
         target_branch: &str,
-    ) -> Result<HashMap<String, FileChange>> {
-        debug!("Retrieving changes between branches");
-
-        let output = self.git.run_git_command(&[
-            "diff",
-            "--name-status",
-            "--no-renames",
-            &format!("{target_branch}...{branch_name}"),
-        ])?;
-
-        let mut changes = HashMap::with_capacity(output.lines().count());
-        for line in output.lines() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let status_str = parts[0];
-                let path_str = parts[1];
-                let file_status = match status_str {
-                    "A" => FileStatus::Added,
-                    "D" => FileStatus::Deleted,
-                    _ => FileStatus::Modified,
-                };
-
-                debug!(
-                    file_path = %path_str,
-                    status = ?file_status,
-                    "Processing file change from diff"
-                );
-
-                changes.insert(
-                    path_str.to_string(),
-                    FileChange {
-                        path: path_str.to_string(),
-                        status: file_status,
-                    },
-                );
-            }
-        }
-        debug!(
-            num_changes = changes.len(),
-            "Completed processing all file changes"
-        );
-        Ok(changes)
+    ) -> Result<impl Iterator<Item = Result<FileChange>> + '_> {
+        self.stream(&DiffSpec::branch(branch, target_branch))
     }
 
-    /// Lists changes in a specific commit
+    /// Streams changes in a specific commit straight from `git diff`, without collecting them
     ///
     /// # Errors
     ///
-    /// Returns an error if the commit changes cannot be retrieved
-    #[instrument(skip(self), fields(commit_hash = %commit_hash))]
-    pub fn list_commit_changes(&self, commit_hash: &str) -> Result<HashMap<String, FileChange>> {
-        debug!("Listing changes for specific commit");
-        self.get_changes_for_commits(commit_hash)
+    /// Returns an error if the commit cannot be found or the diff cannot be started.
+    /// Errors reading the diff are yielded by the iterator.
+    pub fn iter_commit_changes(
+        &self,
+        commit_hash: &str,
+    ) -> Result<impl Iterator<Item = Result<FileChange>> + '_> {
+        self.stream(&DiffSpec::commit(commit_hash))
     }
 
-    /// Exports changes from a specific commit to the specified output directory
+    /// Discovers the default branch of the remote repository (e.g. `origin/main`)
     ///
     /// # Errors
     ///
-    /// Returns an error if the changes cannot be exported or the output directory is not accessible
-    #[instrument(skip(self, output_dir), fields(commit_hash = %commit_hash, output_dir = %output_dir.display()))]
-    pub fn export_commit_changes(
+    /// Returns an error if the default branch cannot be discovered
+    pub fn default_branch(&self) -> Result<String> {
+        self.git.discover_default_branch()
+    }
+
+    /// Gets the repository ready for `spec` (checking out branches, fetching commits) and
+    /// returns the arguments that select it in `git diff`
+    fn resolve_spec(&self, spec: &DiffSpec) -> Result<Vec<String>> {
+        let revs = match spec {
+            DiffSpec::Branch { branch, target } => {
+                let branch = self.prepare_branch(branch)?;
+                self.merge_base(target, &branch)?;
+                vec![format!("{target}...{branch}")]
+            }
+            DiffSpec::BranchVsDefault { branch } => {
+                let branch = self.prepare_branch(branch)?;
+                let target = self.git.discover_default_branch()?;
+                debug!(target_branch = %target, "Discovered default branch");
+                self.merge_base(&target, &branch)?;
+                vec![format!("{target}...{branch}")]
+            }
+            DiffSpec::Commit(commit) => {
+                self.ensure_commit(commit)?;
+                let parent = format!("{commit}^");
+                let found =
+                    self.deepen_until(&format!("the parent of {commit}"), &[commit], || {
+                        self.git
+                            .run_git_command(&["rev-parse", "--verify", "--quiet", &parent])
+                    });
+                match found {
+                    Ok(_) => vec![parent, commit.clone()],
+                    // A root commit adds every file, so it is compared with the empty tree
+                    Err(_) if !self.is_shallow()? && self.is_root_commit(commit)? => {
+                        debug!("Commit has no parent, comparing with the empty tree");
+                        vec![diff::EMPTY_TREE.to_string(), commit.clone()]
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            DiffSpec::Range { from, to } => vec![from.clone(), to.clone()],
+            DiffSpec::MergeBase { base, head } => {
+                let merge_base = self.merge_base(base, head)?;
+                debug!(merge_base = %merge_base, "Resolved merge base");
+                vec![merge_base, head.clone()]
+            }
+            DiffSpec::Worktree => vec!["HEAD".to_string()],
+            DiffSpec::Index => vec!["--cached".to_string(), "HEAD".to_string()],
+        };
+        debug!(revs = ?revs, "Resolved diff spec");
+        Ok(revs)
+    }
+
+    /// Writes the new content of every added and modified file to the output directory, along
+    /// with a `.diff` file for modified ones. Blob contents are streamed from `git cat-file --batch`
+    /// processes (or read from the working tree when git has not hashed them) and the diffs come
+    /// from `git diff` over `diff_revs`, limited to the modified files and split per file.
+    ///
+    /// Files are spread over up to `jobs` worker threads, each with its own cat-file process.
+    /// Every file is attempted; failures are collected and returned together, sorted by path.
+    #[instrument(skip(self, changes, options), fields(diff_revs = ?diff_revs, output_dir = %options.output_dir.display(), jobs = self.jobs))]
+    fn export_changes(
         &self,
-        commit_hash: &str,
-        output_dir: &Path,
-    ) -> Result<HashMap<String, FileChange>> {
-        debug!("Starting export of commit changes");
-        let change_files = self.get_changes_for_commits(commit_hash)?;
+        changes: &mut ChangeSet,
+        diff_revs: &[String],
+        options: &ExportOptions,
+    ) -> Result<()> {
+        let output_root = output::prepare_root(&options.output_dir)?;
+
+        let work: Vec<&FileChange> = changes
+            .iter()
+            .filter(|change| !matches!(change.status, FileStatus::Deleted))
+            .collect();
+
+        let patch_paths: Vec<&str> = if options.write_diffs {
+            work.iter()
+                .filter(|change| {
+                    matches!(
+                        change.status,
+                        FileStatus::Modified | FileStatus::TypeChanged
+                    )
+                })
+                .map(|change| change.path.as_str())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let needs_patches = !patch_paths.is_empty();
+        // Only the exported paths are diffed, so filtered out files are neither buffered nor, in
+        // a partial clone, fetched from the promisor remote
+        let mut patches = HashMap::new();
+        for batch in patch_paths.chunks(PATHSPEC_BATCH) {
+            let pathspecs: Vec<String> = batch
+                .iter()
+                .map(|path| format!(":(literal){path}"))
+                .collect();
+            let mut args = diff::PATCH_DIFF_ARGS.to_vec();
+            args.extend(diff_revs.iter().map(String::as_str));
+            args.push("--");
+            args.extend(pathspecs.iter().map(String::as_str));
+            patches.extend(self.git.diff_per_file(&args)?);
+        }
+
+        if self.git.is_partial_clone() {
+            // Fetch every missing blob in one request instead of one per file
+            let oids: Vec<&str> = work
+                .iter()
+                .filter(|change| change.entry_kind != EntryKind::Gitlink)
+                .filter_map(|change| change.new_oid.as_deref())
+                .collect();
+            self.git.prefetch_objects(&oids)?;
+        }
+
+        // Resolved once here rather than for every LFS pointer the workers come across
+        let lfs_dir = if self.resolve_lfs {
+            Some(self.git_common_dir()?)
+        } else {
+            None
+        };
+
+        let num_workers = self.jobs.clamp(1, work.len().max(1));
+        let blob_readers = (0..num_workers)
+            .map(|_| self.git.blob_reader())
+            .collect::<Result<Vec<_>>>()?;
         debug!(
-            num_files = change_files.len(),
-            "Retrieved changes for commit"
+            num_files = work.len(),
+            num_workers, "Exporting files with worker pool"
         );
 
-        for (path_str, file_status) in &change_files {
-            let output_file_path = output_dir.join(path_str);
-            debug!(
-                file_path = %path_str,
-                status = ?file_status.status,
-                "Processing file change from commit"
-            );
-            match file_status.status {
-                FileStatus::Added => {
-                    self.git.run_git_command_to_file(
-                        &["show", &format!("{commit_hash}:{path_str}")],
-                        &output_file_path,
-                    )?;
-                }
-                FileStatus::Modified => {
-                    self.git.run_git_command_to_file(
-                        &["show", &format!("{commit_hash}:{path_str}")],
-                        &output_file_path,
-                    )?;
-                    let diff_file_path = output_dir.join(format!("{path_str}.diff"));
-                    self.git.run_git_command_to_file(
-                        &[
-                            "diff",
-                            &format!("{commit_hash}^..{commit_hash}"),
-                            "--",
-                            path_str,
-                        ],
-                        &diff_file_path,
-                    )?;
+        let next_index = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(work.len()));
+        let (work, patches, output_root) = (&work, needs_patches.then_some(&patches), &output_root);
+        let lfs_dir = lfs_dir.as_deref();
+        let (next_index, results_ref) = (&next_index, &results);
+        std::thread::scope(|scope| {
+            for mut blobs in blob_readers {
+                scope.spawn(move || loop {
+                    let index = next_index.fetch_add(1, Ordering::Relaxed);
+                    let Some(change) = work.get(index) else {
+                        break;
+                    };
+                    let result =
+                        self.export_file(&mut blobs, patches, output_root, lfs_dir, change);
+                    results_ref
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push((change.path.clone(), result));
+                });
+            }
+        });
+
+        let mut failures = Vec::new();
+        for (path, result) in results.into_inner().unwrap_or_else(PoisonError::into_inner) {
+            match result {
+                Ok(lfs_pointer) => {
+                    if let Some(change) = changes.get_mut(&path) {
+                        change.lfs_pointer = lfs_pointer;
+                    }
                 }
-                FileStatus::Deleted => {
-                    debug!("Skipping export of deleted file from commit");
+                Err(error) => {
+                    debug!(file_path = %path, error = %error, "Failed to export file");
+                    failures.push(ExportFailure { path, error });
                 }
             }
         }
-        debug!(
-            num_files = change_files.len(),
-            "Completed export of all commit changes"
-        );
-        Ok(change_files)
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            failures.sort_by(|a, b| a.path.cmp(&b.path));
+            Err(Error::ExportFailed(failures))
+        }
+    }
+
+    /// Exports a single added or modified file, plus its `.diff` file when it was modified and
+    /// `patches` were requested
+    #[instrument(skip(self, blobs, patches, output_root, lfs_dir, change), fields(file_path = %change.path, status = ?change.status))]
+    fn export_file(
+        &self,
+        blobs: &mut BlobReader,
+        patches: Option<&HashMap<String, Vec<u8>>>,
+        output_root: &Path,
+        lfs_dir: Option<&Path>,
+        change: &FileChange,
+    ) -> Result<Option<LfsPointer>> {
+        debug!("Processing file change");
+        let mut lfs_pointer = None;
+        if change.entry_kind == EntryKind::Gitlink {
+            debug!("Skipping export of submodule content");
+        } else {
+            let output_file_path = output::prepare_destination(output_root, &change.path)?;
+            lfs_pointer = self.export_blob(blobs, change, &output_file_path, lfs_dir)?;
+        }
+
+        let Some(patches) = patches else {
+            return Ok(lfs_pointer);
+        };
+        if matches!(
+            change.status,
+            FileStatus::Modified | FileStatus::TypeChanged
+        ) {
+            let diff_file_path =
+                output::prepare_destination(output_root, &format!("{}.diff", change.path))?;
+            let patch = patches.get(&change.path).map_or(&[][..], Vec::as_slice);
+            output::write_file(&diff_file_path, patch)?;
+        }
+        Ok(lfs_pointer)
+    }
+
+    /// Writes the new content of a change to `output_file_path`, recreating symlinks and
+    /// executable bits, and replacing Git LFS pointers with the real object when it is available
+    /// in the LFS store of the git directory `lfs_dir`.
+    #[instrument(skip(self, blobs, change, output_file_path, lfs_dir), fields(path = %change.path, kind = ?change.entry_kind))]
+    fn export_blob(
+        &self,
+        blobs: &mut BlobReader,
+        change: &FileChange,
+        output_file_path: &Path,
+        lfs_dir: Option<&Path>,
+    ) -> Result<Option<LfsPointer>> {
+        let content = if let Some(oid) = change.new_oid.as_deref() {
+            blobs.read(oid)?.ok_or_else(|| {
+                Error::GitCommandError(format!("Object {oid} for {} not found", change.path))
+            })?
+        } else {
+            // Working tree files are not hashed by `git diff`, read them from disk instead
+            self.read_worktree_file(change)?
+        };
+
+        if change.entry_kind == EntryKind::Symlink {
+            write_symlink(&String::from_utf8_lossy(&content), output_file_path)?;
+            return Ok(None);
+        }
+
+        let pointer = lfs::parse_pointer(&content);
+        let mut lfs_object = None;
+        if let Some((oid, size)) = &pointer {
+            debug!(oid = %oid, size, "Detected Git LFS pointer");
+            if let Some(git_dir) = lfs_dir {
+                lfs_object = verified_lfs_object(git_dir, oid, *size)?;
+            }
+        }
+
+        // Written once, through the same no-follow path as every other file, and marked
+        // executable last so the mode of the LFS object never leaks into the export
+        match &lfs_object {
+            Some(object_path) => {
+                output::copy_file(output_file_path, object_path)?;
+                debug!(object_path = %object_path.display(), "Resolved Git LFS object");
+            }
+            None => output::write_file(output_file_path, &content)?,
+        }
+        if change.entry_kind == EntryKind::Executable {
+            set_executable(output_file_path)?;
+        }
+
+        Ok(pointer.map(|(oid, size)| LfsPointer {
+            oid,
+            size,
+            resolved: lfs_object.is_some(),
+        }))
+    }
+
+    /// Reads the content of a changed file from the working tree (the link target for symlinks)
+    fn read_worktree_file(&self, change: &FileChange) -> Result<Vec<u8>> {
+        let path = self.git.repo_path().join(&change.path);
+        if change.entry_kind == EntryKind::Symlink {
+            let target = std::fs::read_link(&path)?;
+            return Ok(target.to_string_lossy().into_owned().into_bytes());
+        }
+        Ok(std::fs::read(&path)?)
+    }
+
+    fn git_common_dir(&self) -> Result<PathBuf> {
+        let dir = self.git.run_git_command(&[
+            "rev-parse",
+            "--path-format=absolute",
+            "--git-common-dir",
+        ])?;
+        if dir.is_empty() {
+            return Err(Error::GitCommandError(
+                "Could not determine git directory".to_string(),
+            ));
+        }
+        Ok(PathBuf::from(dir))
     }
 
-    #[instrument(skip(self), fields(commit_hash = %commit_hash))]
-    fn get_changes_for_commits(&self, commit_hash: &str) -> Result<HashMap<String, FileChange>> {
-        debug!("Retrieving changes for commit");
+    /// Lists the commits between the two sides of the `git diff` revisions `revs`
+    fn commits(&self, revs: &[String]) -> Result<Vec<Commit>> {
+        let Some(range) = log_range(revs) else {
+            return Ok(Vec::new());
+        };
+        let mut args = log::LOG_ARGS.to_vec();
+        args.extend([range.as_str(), "--"]);
+        let output = self.git.run_git_command(&args)?;
+        let commits = log::parse_log(&output, |path| self.path_filter.matches(path));
+        debug!(num_commits = commits.len(), "Listed commits");
+        Ok(commits)
+    }
 
+    /// Lists the commits of the log `range` that changed `path`
+    fn file_commits(&self, range: &str, path: &str) -> Result<Vec<FileCommit>> {
+        let pathspec = format!(":(literal){path}");
+        let mut args = log::LOG_ARGS.to_vec();
+        args.extend([range, "--", &pathspec]);
+        let output = self.git.run_git_command(&args)?;
+        Ok(log::parse_log(&output, |_| false)
+            .iter()
+            .map(FileCommit::from)
+            .collect())
+    }
+
+    /// Lists the packages of `revision`, or of the index when it is `None`: the directories
+    /// with a `Cargo.toml` or `package.json` that names a package. The manifests are read over
+    /// a single `git cat-file --batch`.
+    fn workspace_packages(&self, revision: Option<&str>) -> Result<Vec<Package>> {
+        let mut blob_reader = self.git.blob_reader()?;
+        let mut packages = Vec::new();
+        for path in self.repo_files(revision)? {
+            let file = path.rsplit('/').next().unwrap_or(&path);
+            if !matches!(file, "Cargo.toml" | "package.json") || path.contains('\n') {
+                continue;
+            }
+            let object = format!("{}:{path}", revision.unwrap_or_default());
+            let Some(content) = blob_reader.read(&object)? else {
+                continue;
+            };
+            let Ok(content) = std::str::from_utf8(&content) else {
+                debug!(path = %path, "Skipping manifest that is not valid UTF-8");
+                continue;
+            };
+            if let Some(package) = Package::from_manifest(&path, content) {
+                packages.push(package);
+            }
+        }
+        debug!(num_packages = packages.len(), "Found workspace packages");
+        Ok(packages)
+    }
+
+    /// Lists the files of `revision`, or of the index when it is `None`. The output is read
+    /// untrimmed so paths that start or end with whitespace are kept intact.
+    fn repo_files(&self, revision: Option<&str>) -> Result<BTreeSet<String>> {
+        let mut stream = match revision {
+            Some(revision) => {
+                self.git
+                    .stream_git_command(&["ls-tree", "-r", "-z", "--name-only", revision])?
+            }
+            None => self.git.stream_git_command(&["ls-files", "-z"])?,
+        };
+        let mut files = BTreeSet::new();
+        while let Some(file) = stream.read_field(0)? {
+            if file.is_empty() {
+                continue;
+            }
+            let file = String::from_utf8(file).map_err(|e| Error::InvalidUtf8 {
+                path: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+            })?;
+            files.insert(file);
+        }
+        stream.finish()?;
+        Ok(files)
+    }
+
+    /// Fetches `commit_hash` from origin unless it already exists locally
+    fn ensure_commit(&self, commit_hash: &str) -> Result<()> {
         // Check if the commit exists locally first
         let commit_exists = self
             .git
@@ -296,51 +827,365 @@ impl GitChangesProcessor {
             self.git
                 .run_git_command(&["fetch", "origin", commit_hash])
                 .map_err(|e| {
-                    debug!(error = %e, "Failed to fetch commit, will try to use local commit");
-                    e
+                    debug!(error = %e, "Failed to fetch commit");
+                    match e {
+                        // Neither local nor on origin (or there is no origin to ask)
+                        Error::GitCommandError(stderr)
+                            if Error::is_missing_remote_object(&stderr) =>
+                        {
+                            Error::RefNotFound {
+                                reference: commit_hash.to_string(),
+                            }
+                        }
+                        e => e,
+                    }
                 })?;
         }
 
-        let mut all_changes = HashMap::new();
-        let parent_commit = format!("{commit_hash}^");
-        debug!(parent_commit = %parent_commit, "Comparing commit with its parent");
-        let output = self.git.run_git_command(&[
-            "diff",
-            "--name-status",
-            "--no-renames",
-            &parent_commit,
-            commit_hash,
-        ])?;
-        for line in output.lines() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let status_str = parts[0];
-                let path_str = parts[1];
-                let file_status = match status_str {
-                    "A" => FileStatus::Added,
-                    "D" => FileStatus::Deleted,
-                    _ => FileStatus::Modified,
+        Ok(())
+    }
+
+    /// Checks out `branch`, or when branch checkout is disabled, returns the ref to compare:
+    /// the local branch if there is one, otherwise `origin/<branch>`
+    fn prepare_branch(&self, branch: &str) -> Result<String> {
+        if self.checkout_branches {
+            self.git.checkout_branch(branch)?;
+            return Ok(branch.to_string());
+        }
+        let has_ref = |name: &str| {
+            self.git
+                .run_git_command(&["rev-parse", "--verify", "--quiet", name])
+                .is_ok()
+        };
+        if !has_ref(&format!("refs/heads/{branch}"))
+            && has_ref(&format!("refs/remotes/origin/{branch}"))
+        {
+            debug!(branch = %branch, "Using remote-tracking branch");
+            return Ok(format!("origin/{branch}"));
+        }
+        Ok(branch.to_string())
+    }
+
+    /// Finds the merge base of `base` and `head`, deepening a shallow clone if needed
+    fn merge_base(&self, base: &str, head: &str) -> Result<String> {
+        self.deepen_until(
+            &format!("a merge base of {base} and {head}"),
+            &[base, head],
+            || {
+                match self.git.run_git_command(&["merge-base", base, head]) {
+                    // `git merge-base` exits silently when the histories are unrelated
+                    Err(Error::GitCommandError(error)) if error.is_empty() => {
+                        Err(Error::NoMergeBase {
+                            base: base.to_string(),
+                            head: head.to_string(),
+                        })
+                    }
+                    result => result,
+                }
+            },
+        )
+    }
+
+    /// Runs `lookup` and, while it fails in a shallow clone, fetches progressively more history
+    /// of `refs` from origin (`git fetch --deepen`) and retries, up to the deepen limit. Once the
+    /// clone is complete the error of `lookup` is returned as is. `refs` must resolve first, so a
+    /// mistyped reference is reported as [`Error::RefNotFound`] without fetching anything.
+    #[instrument(skip(self, lookup), fields(deepen_limit = self.deepen_limit))]
+    fn deepen_until<T>(
+        &self,
+        what: &str,
+        refs: &[&str],
+        mut lookup: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut result = lookup();
+        if result.is_err() {
+            for reference in refs {
+                self.verify_ref(reference)?;
+            }
+        }
+        let mut remote_refs: Option<Vec<String>> = None;
+        let mut deepened = 0;
+        let mut step = INITIAL_DEEPEN;
+        while result.is_err() && self.is_shallow()? {
+            if deepened >= self.deepen_limit {
+                return Err(Error::ShallowHistory {
+                    what: what.to_string(),
+                    depth: deepened,
+                });
+            }
+            let remote_refs = remote_refs.get_or_insert_with(|| {
+                refs.iter()
+                    .filter_map(|reference| self.remote_ref(reference))
+                    .collect()
+            });
+            step = step.min(self.deepen_limit - deepened);
+            debug!(step, deepened, refs = ?remote_refs, "History is shallow, fetching more commits");
+            // Without a counterpart on origin, deepen what a plain fetch brings in
+            let deepen = format!("--deepen={step}");
+            let mut args = vec!["fetch", &deepen, "origin"];
+            args.extend(remote_refs.iter().map(String::as_str));
+            self.git.run_git_command(&args)?;
+            deepened += step;
+            step = step.saturating_mul(2);
+            result = lookup();
+        }
+        result
+    }
+
+    /// Resolves `reference` to a commit, or returns [`Error::RefNotFound`]
+    fn verify_ref(&self, reference: &str) -> Result<String> {
+        self.git
+            .run_git_command(&[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{reference}^{{commit}}"),
+            ])
+            .map_err(|e| match e {
+                Error::GitCommandError(_) => Error::RefNotFound {
+                    reference: reference.to_string(),
+                },
+                e => e,
+            })
+    }
+
+    /// What to fetch from origin to deepen the history of `reference`: the branch or tag of the
+    /// same name, or the commit when `reference` is a commit id. `None` when origin has no
+    /// counterpart, e.g. for a local branch.
+    fn remote_ref(&self, reference: &str) -> Option<String> {
+        let has_ref = |name: &str| {
+            self.git
+                .run_git_command(&["rev-parse", "--verify", "--quiet", name])
+                .is_ok()
+        };
+        let branch = reference
+            .strip_prefix("refs/remotes/origin/")
+            .or_else(|| reference.strip_prefix("origin/"))
+            .unwrap_or(reference);
+        if has_ref(&format!("refs/remotes/origin/{branch}")) {
+            return Some(format!("refs/heads/{branch}"));
+        }
+        let tag = reference.strip_prefix("refs/tags/").unwrap_or(reference);
+        if has_ref(&format!("refs/tags/{tag}")) {
+            return Some(format!("refs/tags/{tag}"));
+        }
+        if reference.len() >= 7 && reference.chars().all(|c| c.is_ascii_hexdigit()) {
+            return self.verify_ref(reference).ok();
+        }
+        debug!(reference = %reference, "No counterpart on origin to deepen");
+        None
+    }
+
+    /// Returns true when `commit` has no parents. In a shallow clone this is also true of the
+    /// commits at the shallow boundary.
+    fn is_root_commit(&self, commit: &str) -> Result<bool> {
+        let parents =
+            self.git
+                .run_git_command(&["rev-list", "--parents", "--max-count=1", commit])?;
+        Ok(parents.split_whitespace().count() == 1)
+    }
+
+    fn is_shallow(&self) -> Result<bool> {
+        Ok(self
+            .git
+            .run_git_command(&["rev-parse", "--is-shallow-repository"])?
+            == "true")
+    }
+
+    /// Streams the changes of the repository over `revs` that are kept by the path filter,
+    /// with their owners and commits when code owner lookup and commit attribution are enabled
+    fn filtered_changes(
+        &self,
+        revs: &[String],
+    ) -> Result<impl Iterator<Item = Result<FileChange>> + '_> {
+        let owners = if self.code_owners {
+            Some(self.code_owners(base_revision(revs))?.unwrap_or_default())
+        } else {
+            None
+        };
+        let range = log_range(revs);
+        let mut commits_by_path: HashMap<String, Vec<FileCommit>> = HashMap::new();
+        if self.commit_attribution == Some(CommitAttribution::Cheap) {
+            for commit in self.commits(revs)? {
+                for path in &commit.paths {
+                    commits_by_path
+                        .entry(path.clone())
+                        .or_default()
+                        .push(FileCommit::from(&commit));
+                }
+            }
+        }
+        Ok(self
+            .stream_changes(&self.git, revs)?
+            .filter(|change| {
+                change
+                    .as_ref()
+                    .map_or(true, |change| self.path_filter.matches(&change.path))
+            })
+            .map(move |change| {
+                let mut change = change?;
+                if let Some(owners) = &owners {
+                    change.owners = Some(owners.owners_of(&change.path).to_vec());
+                }
+                change.commits = match (self.commit_attribution, &range) {
+                    (None, _) => None,
+                    (Some(_), None) => Some(Vec::new()),
+                    (Some(CommitAttribution::Cheap), Some(_)) => Some(
+                        commits_by_path
+                            .get(&change.path)
+                            .cloned()
+                            .unwrap_or_default(),
+                    ),
+                    (Some(CommitAttribution::Precise), Some(range)) => {
+                        Some(self.file_commits(range, &change.path)?)
+                    }
                 };
+                Ok(change)
+            }))
+    }
+
+    /// Streams the changes of `git diff` over `revs` in `git`'s repository as they are produced
+    fn stream_changes<'a>(
+        &'a self,
+        git: &'a GitCli,
+        revs: &[String],
+    ) -> Result<impl Iterator<Item = Result<FileChange>> + 'a> {
+        let mut args = diff::RAW_DIFF_ARGS.to_vec();
+        args.extend(revs.iter().map(String::as_str));
+        let reader = diff::RawDiffReader::new(git.stream_git_command(&args)?);
+        Ok(reader.map(move |change| {
+            let mut change = change?;
+            self.fill_submodule_changes(git, &mut change);
+            Ok(change)
+        }))
+    }
+
+    /// Fills in the nested changes of a submodule entry when recursion is enabled
+    fn fill_submodule_changes(&self, git: &GitCli, change: &mut FileChange) {
+        if !self.recurse_submodules {
+            return;
+        }
+        let path_str = change.path.as_str();
+        let Some(submodule) = change.submodule.as_mut() else {
+            return;
+        };
+        let Some(new_commit) = submodule.new_commit.as_deref() else {
+            debug!(submodule = %path_str, "Submodule was removed, skipping recursion");
+            return;
+        };
+        let submodule_path = git.repo_path().join(path_str);
+        if !submodule_path.join(".git").exists() {
+            debug!(submodule = %path_str, "Submodule is not checked out, skipping recursion");
+            return;
+        }
 
-                debug!(
-                    file_path = %path_str,
-                    status = ?file_status,
-                    "Processing file change from commit diff"
-                );
-
-                all_changes.insert(
-                    path_str.to_string(),
-                    FileChange {
-                        path: path_str.to_string(),
-                        status: file_status,
-                    },
-                );
+        let submodule_git = git.for_path(submodule_path);
+        let old_commit = submodule.old_commit.as_deref().unwrap_or(diff::EMPTY_TREE);
+        let nested = self
+            .stream_changes(
+                &submodule_git,
+                &[old_commit.to_string(), new_commit.to_string()],
+            )
+            .and_then(Iterator::collect::<Result<Vec<_>>>);
+        let mut nested = match nested {
+            Ok(nested) => nested,
+            Err(e) => {
+                debug!(submodule = %path_str, error = %e, "Submodule commits not available locally");
+                return;
             }
+        };
+
+        for nested_change in &mut nested {
+            prefix_paths(nested_change, path_str);
         }
-        debug!(
-            num_changes = all_changes.len(),
-            "Completed processing all commit changes"
-        );
-        Ok(all_changes)
+        nested.sort_by(|a, b| a.path.cmp(&b.path));
+        debug!(submodule = %path_str, num_changes = nested.len(), "Collected submodule changes");
+        submodule.changes = nested;
+    }
+}
+
+/// The old side of the `git diff` revisions produced by `resolve_spec`
+fn base_revision(revs: &[String]) -> &str {
+    revs.iter()
+        .find(|rev| !rev.starts_with('-'))
+        .and_then(|rev| rev.split("...").next())
+        .unwrap_or("HEAD")
+}
+
+/// The new side of the `git diff` revisions produced by `resolve_spec`, or `None` when it is the
+/// working tree or the index
+fn head_revision(revs: &[String]) -> Option<&str> {
+    match revs {
+        [range] => range.split_once("...").map(|(_, head)| head),
+        [flag, _] if flag.starts_with('-') => None,
+        [.., head] => Some(head),
+        [] => None,
+    }
+}
+
+/// The `git log` range of the commits between the two sides of the `git diff` revisions
+/// produced by `resolve_spec`, or `None` when the new side is the working tree or the index
+fn log_range(revs: &[String]) -> Option<String> {
+    match revs {
+        [range] => range
+            .split_once("...")
+            .map(|(base, head)| format!("{base}..{head}")),
+        // A root commit is compared with the empty tree, and is the only commit of its history
+        [from, to] if from == diff::EMPTY_TREE => Some(to.clone()),
+        [from, to] if !from.starts_with('-') => Some(format!("{from}..{to}")),
+        _ => None,
+    }
+}
+
+/// Prefixes the path of a change, and of any nested submodule changes, with `prefix/`
+fn prefix_paths(change: &mut FileChange, prefix: &str) {
+    change.path = format!("{prefix}/{}", change.path);
+    if let Some(submodule) = change.submodule.as_mut() {
+        for nested in &mut submodule.changes {
+            prefix_paths(nested, prefix);
+        }
+    }
+}
+
+/// Returns the path of the Git LFS object `oid` in the LFS store of `git_dir` when it is there
+/// and matches the `size` and hash of its pointer
+fn verified_lfs_object(git_dir: &Path, oid: &str, size: u64) -> Result<Option<PathBuf>> {
+    let Some(object_path) = lfs::object_path(git_dir, oid) else {
+        return Ok(None);
+    };
+    if !object_path.is_file() {
+        debug!(object_path = %object_path.display(), "Git LFS object not found locally");
+        return Ok(None);
+    }
+    if !lfs::verify_object(&object_path, oid, size)? {
+        debug!(object_path = %object_path.display(), "Git LFS object does not match its pointer");
+        return Ok(None);
+    }
+    Ok(Some(object_path))
+}
+
+/// Recreates a symlink pointing at `link_target`. On platforms without symlink support the
+/// target is written as a regular file, which is what `git` does with `core.symlinks=false`.
+fn write_symlink(link_target: &str, output_file_path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(link_target, output_file_path)
+        .map_err(output::write_error(output_file_path))?;
+    #[cfg(not(unix))]
+    std::fs::write(output_file_path, link_target).map_err(output::write_error(output_file_path))?;
+
+    debug!(link_target = %link_target, "Symlink recreated");
+    Ok(())
+}
+
+/// Marks an exported file as executable (no-op on platforms without unix permissions)
+fn set_executable(output_file_path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(output_file_path, std::fs::Permissions::from_mode(0o755))
+            .map_err(output::write_error(output_file_path))?;
     }
+    #[cfg(not(unix))]
+    let _ = output_file_path;
+    Ok(())
 }
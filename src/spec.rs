@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+/// What to compare when listing or exporting changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSpec {
+    /// Changes on `branch` since it diverged from `target` (`target...branch`).
+    /// The branch is checked out first.
+    Branch { branch: String, target: String },
+    /// Changes on `branch` since it diverged from the remote's default branch.
+    /// The branch is checked out first.
+    BranchVsDefault { branch: String },
+    /// Changes introduced by a single commit, compared with its first parent.
+    /// The commit is fetched from origin when it is not available locally.
+    Commit(String),
+    /// Changes between the trees of two revisions (`from..to`)
+    Range { from: String, to: String },
+    /// Changes on `head` since its merge base with `base` (`base...head`), without checking
+    /// anything out
+    MergeBase { base: String, head: String },
+    /// Uncommitted changes to tracked files in the working tree, compared with `HEAD`
+    Worktree,
+    /// Staged changes, compared with `HEAD`
+    Index,
+}
+
+impl DiffSpec {
+    /// Changes on `branch` since it diverged from `target`
+    #[must_use]
+    pub fn branch(branch: impl Into<String>, target: impl Into<String>) -> Self {
+        Self::Branch {
+            branch: branch.into(),
+            target: target.into(),
+        }
+    }
+
+    /// Changes on `branch` since it diverged from the default branch
+    #[must_use]
+    pub fn branch_vs_default(branch: impl Into<String>) -> Self {
+        Self::BranchVsDefault {
+            branch: branch.into(),
+        }
+    }
+
+    /// Changes introduced by `commit`
+    #[must_use]
+    pub fn commit(commit: impl Into<String>) -> Self {
+        Self::Commit(commit.into())
+    }
+
+    /// Changes between the trees of `from` and `to`
+    #[must_use]
+    pub fn range(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self::Range {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    /// Changes on `head` since its merge base with `base`
+    #[must_use]
+    pub fn merge_base(base: impl Into<String>, head: impl Into<String>) -> Self {
+        Self::MergeBase {
+            base: base.into(),
+            head: head.into(),
+        }
+    }
+
+    /// Parses revision range notation: `from..to` becomes [`DiffSpec::Range`] and
+    /// `base...head` becomes [`DiffSpec::MergeBase`]. Returns `None` when either side is missing.
+    #[must_use]
+    pub fn parse_range(range: &str) -> Option<Self> {
+        if let Some((base, head)) = range.split_once("...") {
+            return (!base.is_empty() && !head.is_empty()).then(|| Self::merge_base(base, head));
+        }
+        let (from, to) = range.split_once("..")?;
+        (!from.is_empty() && !to.is_empty()).then(|| Self::range(from, to))
+    }
+}
+
+/// Options for [`crate::processor::GitChangesProcessor::export`]
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub(crate) output_dir: PathBuf,
+    pub(crate) write_diffs: bool,
+    pub(crate) manifest: Option<PathBuf>,
+}
+
+impl ExportOptions {
+    /// Exports into `output_dir`, creating it if needed
+    #[must_use]
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            write_diffs: true,
+            manifest: None,
+        }
+    }
+
+    /// Enables or disables writing a `.diff` file next to every modified file (enabled by default)
+    #[must_use]
+    pub const fn with_diffs(mut self, enabled: bool) -> Self {
+        self.write_diffs = enabled;
+        self
+    }
+
+    /// Writes a JSON manifest of the export to `path`: the exported changes, and the paths of
+    /// the Git LFS objects that could not be resolved and were exported as pointers
+    #[must_use]
+    pub fn with_manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest = Some(path.into());
+        self
+    }
+
+    /// Directory the changes are exported into
+    #[must_use]
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+}
@@ -1,12 +1,155 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileStatus {
     Added,
     Modified,
     Deleted,
+    /// The entry changed type, e.g. a regular file replaced by a symlink
+    TypeChanged,
+}
+
+/// Kind of tree entry, derived from its git mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Executable,
+    Symlink,
+    Gitlink,
 }
 
-#[derive(Debug)]
+impl EntryKind {
+    /// Maps a git tree entry mode (e.g. `100644`) to its kind
+    #[must_use]
+    pub fn from_mode(mode: &str) -> Option<Self> {
+        match mode {
+            "100644" | "100664" => Some(Self::File),
+            "100755" => Some(Self::Executable),
+            "120000" => Some(Self::Symlink),
+            "160000" => Some(Self::Gitlink),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileChange {
     pub path: String,
     pub status: FileStatus,
+    /// Git mode of the entry before the change (`None` when it was added)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_mode: Option<String>,
+    /// Git mode of the entry after the change (`None` when it was deleted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_mode: Option<String>,
+    /// Kind of the entry after the change, or before it for deletions
+    pub entry_kind: EntryKind,
+    /// Object id of the entry before the change (`None` when it was added)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_oid: Option<String>,
+    /// Object id of the entry after the change (`None` when it was deleted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_oid: Option<String>,
+    /// Git LFS pointer details, set when the exported blob is an LFS pointer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lfs_pointer: Option<LfsPointer>,
+    /// Submodule details, set when the path is a submodule (gitlink)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submodule: Option<SubmoduleChange>,
+    /// Owners of the path from `CODEOWNERS`, set when code owner lookup is enabled. Empty when
+    /// no rule assigns an owner.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owners: Option<Vec<String>>,
+    /// Commits of the comparison that changed the path, children before parents. Set when
+    /// commit attribution is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commits: Option<Vec<FileCommit>>,
+}
+
+impl FileChange {
+    /// Returns true when the entry's mode changed, e.g. after `chmod +x`
+    #[must_use]
+    pub fn mode_changed(&self) -> bool {
+        matches!((&self.old_mode, &self.new_mode), (Some(old), Some(new)) if old != new)
+    }
+}
+
+/// A Git LFS pointer found while exporting a file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LfsPointer {
+    /// Object id of the LFS object (e.g. `sha256:4d7a...`)
+    pub oid: String,
+    /// Size in bytes of the real object
+    pub size: u64,
+    /// Whether the real object was found in the local LFS store and written to the output
+    pub resolved: bool,
+}
+
+/// A change to a submodule (gitlink) entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmoduleChange {
+    /// Commit the submodule pointed to before the change (`None` when it was added)
+    pub old_commit: Option<String>,
+    /// Commit the submodule points to after the change (`None` when it was deleted)
+    pub new_commit: Option<String>,
+    /// Changes inside the submodule, with paths prefixed by the submodule path.
+    /// Only populated when submodule recursion is enabled and the submodule is checked out locally.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FileChange>,
+}
+
+/// A commit of a comparison, see [`crate::processor::GitChangesProcessor::list_commits`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Commit {
+    pub sha: String,
+    /// Parent commits, several for a merge
+    pub parents: Vec<String>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// First line of the message
+    pub subject: String,
+    /// Rest of the message, trailers included
+    pub body: String,
+    /// `Key: value` lines at the end of the message, e.g. `Signed-off-by` or `Co-authored-by`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trailers: Vec<Trailer>,
+    /// Paths the commit changed relative to its first parent. Empty for merges, like
+    /// `git log --name-only`.
+    pub paths: Vec<String>,
+}
+
+/// A commit that changed a file, see [`FileChange::commits`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileCommit {
+    pub sha: String,
+    pub author: Signature,
+    pub subject: String,
+}
+
+impl From<&Commit> for FileCommit {
+    fn from(commit: &Commit) -> Self {
+        Self {
+            sha: commit.sha.clone(),
+            author: commit.author.clone(),
+            subject: commit.subject.clone(),
+        }
+    }
+}
+
+/// Who authored or committed a commit, and when
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Date in strict ISO 8601 format, e.g. `2024-05-01T12:30:00+02:00`
+    pub date: String,
+}
+
+/// A trailer of a commit message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
 }